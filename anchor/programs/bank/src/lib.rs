@@ -3,77 +3,282 @@ use anchor_lang::system_program;
 
 declare_id!("3d6TUS2v5bmZ9489ii1dsasfPossE2zUGhaWjr2gFBKW");
 
+pub const ANCHOR_DISCRIMINATOR_SIZE: usize = 8;
+// 旧版 UserAccount：discriminator + deposit_amount
+pub const LEGACY_USER_ACCOUNT_SIZE: usize = ANCHOR_DISCRIMINATOR_SIZE + 8;
+// 利息指数的精度，初始值为 1.0
+pub const INTEREST_INDEX_PRECISION: u128 = 1_000_000_000_000;
+// 按 400ms 一个 slot 估算的年 slot 数
+pub const SLOTS_PER_YEAR: u64 = 78_840_000;
+pub const MAX_INTEREST_RATE_BPS: u16 = 10_000;
+// 提款延迟上限 30 天，防止管理员无限期锁定大额提款或 unlock_ts 溢出
+pub const MAX_WITHDRAW_DELAY: i64 = 30 * 24 * 60 * 60;
+
 // Bank 是一个空的 PDA（由 System Program 拥有），仅用于存储 SOL
 // 使用 system_program::transfer 进行存取款操作
 // UserAccount 记录每个用户的存款金额
+// BankState 保存管理员配置的限额和全局统计
+// bank PDA 始终保留免租金所需的最低余额（reserve），这部分不属于任何用户
+// 利息由 admin 注入 interest_pool，按全局指数累计，存取款前先结算到用户余额
+// 达到阈值的大额提款需先 request_withdraw，延迟到期后才能 execute_withdraw
 
 #[program]
 pub mod bank {
     use super::*;
 
-    pub fn create_user_account(ctx: Context<CreateUserAccount>) -> Result<()> {
-        let user_account = &mut ctx.accounts.user_account;
-        user_account.deposit_amount = 0;
+    pub fn initialize(ctx: Context<Initialize>) -> Result<()> {
+        let state = &mut ctx.accounts.state;
+        state.admin = ctx.accounts.admin.key();
+        state.pending_admin = None;
+        state.total_deposits = 0;
+        state.user_count = 0;
+        state.created_slot = Clock::get()?.slot;
+        state.tvl_cap = 0;
+        state.max_user_balance = 0;
+        state.epoch_withdraw_limit = 0;
+        state.withdraw_epoch = 0;
+        state.epoch_withdrawn = 0;
+        state.interest_rate_bps = 0;
+        state.interest_index = INTEREST_INDEX_PRECISION;
+        state.last_accrual_slot = state.created_slot;
+        state.interest_pool = 0;
+        state.large_withdraw_threshold = 0;
+        state.withdraw_delay = 0;
+        state.pending_withdrawals = 0;
+
+        // reserve 始终由 admin 单独存入，bank PDA 中已有的旧存款不能被当作 reserve
+        system_program::transfer(
+            CpiContext::new(
+                system_program::ID,
+                system_program::Transfer {
+                    from: ctx.accounts.admin.to_account_info(),
+                    to: ctx.accounts.bank.to_account_info(),
+                },
+            ),
+            bank_reserve()?,
+        )?;
         Ok(())
     }
 
-    pub fn deposit(ctx: Context<Deposit>, amount: u64) -> Result<()> {
-        // 使用 system_program::transfer 从用户转账到 bank PDA
-        // System Program 检测到目标账户不存在，自动创建：
-        // - Owner: System Program (11111...1111)
-        // - Lamports: amount
-        // - Data: []
+    // 校验 bank PDA 扣除 reserve 后的余额足以覆盖所有用户存款及利息池
+    pub fn check_solvency(ctx: Context<CheckSolvency>) -> Result<()> {
+        ctx.accounts
+            .state
+            .check_solvency(ctx.accounts.bank.lamports(), bank_reserve()?)
+    }
+
+    // 两步转移 admin，新 admin 可以是多签 PDA，通过 CPI 签名接受
+    pub fn propose_admin(ctx: Context<AdminOnly>, new_admin: Pubkey) -> Result<()> {
+        ctx.accounts.state.pending_admin = Some(new_admin);
+        Ok(())
+    }
+
+    pub fn accept_admin(ctx: Context<AcceptAdmin>) -> Result<()> {
+        ctx.accounts
+            .state
+            .accept_admin(ctx.accounts.new_admin.key())
+    }
+
+    // 设置限额，0 表示不限制
+    pub fn set_limits(
+        ctx: Context<AdminOnly>,
+        tvl_cap: u64,
+        max_user_balance: u64,
+        epoch_withdraw_limit: u64,
+    ) -> Result<()> {
+        let state = &mut ctx.accounts.state;
+        state.tvl_cap = tvl_cap;
+        state.max_user_balance = max_user_balance;
+        state.epoch_withdraw_limit = epoch_withdraw_limit;
+        Ok(())
+    }
+
+    // 设置大额提款阈值和延迟秒数，阈值为 0 表示所有提款都可即时完成，延迟不得超过 MAX_WITHDRAW_DELAY
+    pub fn set_withdraw_delay(
+        ctx: Context<AdminOnly>,
+        large_withdraw_threshold: u64,
+        withdraw_delay: i64,
+    ) -> Result<()> {
+        ctx.accounts
+            .state
+            .set_withdraw_delay(large_withdraw_threshold, withdraw_delay)
+    }
+
+    // 设置年化利率，先按旧利率累计到当前 slot
+    pub fn set_interest_rate(ctx: Context<AdminOnly>, rate_bps: u16) -> Result<()> {
+        require!(
+            rate_bps <= MAX_INTEREST_RATE_BPS,
+            BankError::InvalidInterestRate
+        );
+        let state = &mut ctx.accounts.state;
+        state.accrue_interest(Clock::get()?.slot)?;
+        state.interest_rate_bps = rate_bps;
+        Ok(())
+    }
+
+    // 经 admin 授权，由 payer 向 bank PDA 注入用于支付利息的 lamports
+    pub fn fund_interest(ctx: Context<FundInterest>, amount: u64) -> Result<()> {
         system_program::transfer(
             CpiContext::new(
                 system_program::ID,
                 system_program::Transfer {
-                    from: ctx.accounts.depositor.to_account_info(),
+                    from: ctx.accounts.payer.to_account_info(),
                     to: ctx.accounts.bank.to_account_info(),
                 },
             ),
             amount,
         )?;
 
+        let state = &mut ctx.accounts.state;
+        state.interest_pool = state
+            .interest_pool
+            .checked_add(amount)
+            .ok_or(BankError::MathOverflow)?;
+        Ok(())
+    }
+
+    // deposit 会在首次存款时自动创建 UserAccount，这里保留用于兼容
+    pub fn create_user_account(ctx: Context<CreateUserAccount>) -> Result<()> {
+        let state = &mut ctx.accounts.state;
+        state.accrue_interest(Clock::get()?.slot)?;
+
+        let user_account = &mut ctx.accounts.user_account;
+        user_account.deposit_amount = 0;
+        user_account.interest_index = state.interest_index;
+
+        state.user_count = state
+            .user_count
+            .checked_add(1)
+            .ok_or(BankError::MathOverflow)?;
+        Ok(())
+    }
+
+    // 把旧版 UserAccount 扩容为带利息指数的新格式，新增的租金由 owner 支付
+    pub fn migrate_user_account(ctx: Context<MigrateUserAccount>) -> Result<()> {
+        let info = ctx.accounts.user_account.to_account_info();
+        require_keys_eq!(*info.owner, crate::ID, BankError::InvalidLegacyAccount);
+        let deposit_amount = legacy_deposit_amount(&info.try_borrow_data()?)?;
+
+        let state = &mut ctx.accounts.state;
+        state.accrue_interest(Clock::get()?.slot)?;
+        state.record_migration(deposit_amount)?;
+
+        let space = ANCHOR_DISCRIMINATOR_SIZE + UserAccount::INIT_SPACE;
+        let shortfall = Rent::get()?
+            .minimum_balance(space)
+            .saturating_sub(info.lamports());
+        if shortfall > 0 {
+            system_program::transfer(
+                CpiContext::new(
+                    system_program::ID,
+                    system_program::Transfer {
+                        from: ctx.accounts.owner.to_account_info(),
+                        to: info.clone(),
+                    },
+                ),
+                shortfall,
+            )?;
+        }
+        info.resize(space)?;
+
+        // 旧余额从当前指数开始计息
+        let user_account = UserAccount {
+            deposit_amount,
+            interest_index: state.interest_index,
+            instant_window_start: 0,
+            instant_withdrawn: 0,
+        };
+        user_account.try_serialize(&mut &mut info.try_borrow_mut_data()?[..])?;
+        Ok(())
+    }
+
+    pub fn deposit(ctx: Context<Deposit>, amount: u64) -> Result<()> {
+        take_deposit(
+            &ctx.accounts.bank,
+            &mut ctx.accounts.state,
+            &mut ctx.accounts.user_account,
+            ctx.accounts.depositor.to_account_info(),
+            amount,
+        )
+    }
+
+    // payer 出资，记入 beneficiary 的 UserAccount（不存在时创建）
+    pub fn deposit_for(ctx: Context<DepositFor>, beneficiary: Pubkey, amount: u64) -> Result<()> {
+        take_deposit(
+            &ctx.accounts.bank,
+            &mut ctx.accounts.state,
+            &mut ctx.accounts.user_account,
+            ctx.accounts.payer.to_account_info(),
+            amount,
+        )?;
+
+        emit!(DepositForEvent {
+            payer: ctx.accounts.payer.key(),
+            beneficiary,
+            amount,
+        });
+        Ok(())
+    }
+
+    pub fn withdraw(ctx: Context<Withdraw>, amount: u64) -> Result<()> {
+        ctx.accounts
+            .state
+            .settle_interest(&mut ctx.accounts.user_account, Clock::get()?.slot)?;
+
+        // 确保用户有足够的存款
+        require!(
+            ctx.accounts.user_account.deposit_amount >= amount,
+            BankError::InsufficientFunds
+        );
+        ctx.accounts.state.record_instant_withdrawal(
+            &mut ctx.accounts.user_account,
+            amount,
+            Clock::get()?.unix_timestamp,
+        )?;
+
+        pay_out(
+            &ctx.accounts.bank,
+            ctx.bumps.bank,
+            &mut ctx.accounts.state,
+            ctx.accounts.receiver.to_account_info(),
+            amount,
+        )?;
+
         // 更新用户存款记录
         ctx.accounts.user_account.deposit_amount = ctx
             .accounts
             .user_account
             .deposit_amount
-            .checked_add(amount)
+            .checked_sub(amount)
             .unwrap();
 
         Ok(())
     }
 
-    pub fn withdraw(ctx: Context<Withdraw>, amount: u64) -> Result<()> {
-        // 确保用户有足够的存款
+    // 由 owner 签名，把存款直接提取到 recipient（冷钱包、商户等）
+    pub fn withdraw_to(ctx: Context<WithdrawTo>, amount: u64) -> Result<()> {
+        ctx.accounts
+            .state
+            .settle_interest(&mut ctx.accounts.user_account, Clock::get()?.slot)?;
+
         require!(
             ctx.accounts.user_account.deposit_amount >= amount,
             BankError::InsufficientFunds
         );
+        ctx.accounts.state.record_instant_withdrawal(
+            &mut ctx.accounts.user_account,
+            amount,
+            Clock::get()?.unix_timestamp,
+        )?;
 
-        // 确保 bank 账户有足够的余额
-        let bank_lamports = ctx.accounts.bank.lamports();
-        require!(bank_lamports >= amount, BankError::InsufficientBankFunds);
-
-        // 使用 system_program::transfer 配合 PDA seeds 签名进行转账
-        // 这是推荐的做法
-        let seeds = &[b"bank".as_ref(), &[ctx.bumps.bank]];
-        let signer_seeds = &[&seeds[..]];
-
-        system_program::transfer(
-            CpiContext::new_with_signer(
-                system_program::ID,
-                system_program::Transfer {
-                    from: ctx.accounts.bank.to_account_info(),
-                    to: ctx.accounts.receiver.to_account_info(),
-                },
-                signer_seeds,
-            ),
+        pay_out(
+            &ctx.accounts.bank,
+            ctx.bumps.bank,
+            &mut ctx.accounts.state,
+            ctx.accounts.recipient.to_account_info(),
             amount,
         )?;
 
-        // 更新用户存款记录
         ctx.accounts.user_account.deposit_amount = ctx
             .accounts
             .user_account
@@ -83,6 +288,307 @@ pub mod bank {
 
         Ok(())
     }
+
+    // 用户之间的账本转账，不移动 bank PDA 的 lamports
+    pub fn transfer_internal(ctx: Context<TransferInternal>, to: Pubkey, amount: u64) -> Result<()> {
+        // 同一个账户会被加载两次，退出时后写入的一方会覆盖前者
+        require_keys_neq!(to, ctx.accounts.owner.key(), BankError::SelfTransfer);
+
+        let slot = Clock::get()?.slot;
+        let state = &mut ctx.accounts.state;
+        state.settle_interest(&mut ctx.accounts.user_account, slot)?;
+        state.settle_interest(&mut ctx.accounts.to_user_account, slot)?;
+
+        require!(
+            ctx.accounts.user_account.deposit_amount >= amount,
+            BankError::InsufficientFunds
+        );
+        // 转出也计入发送方的即时提款额度，避免拆给新账户绕过大额提款延迟
+        let now = Clock::get()?.unix_timestamp;
+        state.record_instant_withdrawal(&mut ctx.accounts.user_account, amount, now)?;
+
+        if is_new_account(&ctx.accounts.to_user_account.to_account_info())? {
+            let state = &mut ctx.accounts.state;
+            state.user_count = state
+                .user_count
+                .checked_add(1)
+                .ok_or(BankError::MathOverflow)?;
+        }
+
+        let new_balance = ctx
+            .accounts
+            .to_user_account
+            .deposit_amount
+            .checked_add(amount)
+            .ok_or(BankError::MathOverflow)?;
+        ctx.accounts.state.check_user_balance(new_balance)?;
+
+        ctx.accounts.user_account.deposit_amount -= amount;
+        ctx.accounts.to_user_account.deposit_amount = new_balance;
+
+        Ok(())
+    }
+
+    // 余额为 0 时关闭 UserAccount，租金退还给 owner
+    pub fn close_user_account(ctx: Context<CloseUserAccount>) -> Result<()> {
+        require!(
+            ctx.accounts.user_account.deposit_amount == 0,
+            BankError::AccountNotEmpty
+        );
+        ctx.accounts.state.remove_user()
+    }
+
+    // 取出全部存款并在同一条指令中关闭 UserAccount
+    pub fn withdraw_all_and_close(ctx: Context<WithdrawAllAndClose>) -> Result<()> {
+        ctx.accounts
+            .state
+            .settle_interest(&mut ctx.accounts.user_account, Clock::get()?.slot)?;
+
+        let amount = ctx.accounts.user_account.deposit_amount;
+        if amount > 0 {
+            ctx.accounts.state.record_instant_withdrawal(
+                &mut ctx.accounts.user_account,
+                amount,
+                Clock::get()?.unix_timestamp,
+            )?;
+            pay_out(
+                &ctx.accounts.bank,
+                ctx.bumps.bank,
+                &mut ctx.accounts.state,
+                ctx.accounts.owner.to_account_info(),
+                amount,
+            )?;
+            ctx.accounts.user_account.deposit_amount = 0;
+        }
+        ctx.accounts.state.remove_user()
+    }
+
+    // 大额提款第一步：从用户余额中扣除并锁定到 PendingWithdrawal
+    pub fn request_withdraw(
+        ctx: Context<RequestWithdraw>,
+        request_id: u64,
+        amount: u64,
+    ) -> Result<()> {
+        let state = &mut ctx.accounts.state;
+        state.settle_interest(&mut ctx.accounts.user_account, Clock::get()?.slot)?;
+
+        require!(amount > 0, BankError::InvalidAmount);
+        let user_account = &mut ctx.accounts.user_account;
+        user_account.deposit_amount = user_account
+            .deposit_amount
+            .checked_sub(amount)
+            .ok_or(BankError::InsufficientFunds)?;
+        state.reserve_withdrawal(amount)?;
+
+        let pending = &mut ctx.accounts.pending_withdrawal;
+        pending.owner = ctx.accounts.owner.key();
+        pending.request_id = request_id;
+        pending.amount = amount;
+        pending.unlock_ts = Clock::get()?
+            .unix_timestamp
+            .checked_add(state.withdraw_delay)
+            .ok_or(BankError::MathOverflow)?;
+        Ok(())
+    }
+
+    // 延迟到期后支付给 owner，并关闭 PendingWithdrawal
+    pub fn execute_withdraw(ctx: Context<ExecuteWithdraw>, _request_id: u64) -> Result<()> {
+        let pending = &ctx.accounts.pending_withdrawal;
+        require!(
+            Clock::get()?.unix_timestamp >= pending.unlock_ts,
+            BankError::WithdrawalLocked
+        );
+
+        let amount = pending.amount;
+        ctx.accounts.state.release_withdrawal(amount)?;
+        pay_out(
+            &ctx.accounts.bank,
+            ctx.bumps.bank,
+            &mut ctx.accounts.state,
+            ctx.accounts.owner.to_account_info(),
+            amount,
+        )
+    }
+
+    // 取消提款，锁定的金额退回用户余额
+    pub fn cancel_withdraw(ctx: Context<CancelWithdraw>, _request_id: u64) -> Result<()> {
+        let amount = ctx.accounts.pending_withdrawal.amount;
+        let state = &mut ctx.accounts.state;
+        state.settle_interest(&mut ctx.accounts.user_account, Clock::get()?.slot)?;
+        state.release_withdrawal(amount)?;
+
+        let user_account = &mut ctx.accounts.user_account;
+        user_account.deposit_amount = user_account
+            .deposit_amount
+            .checked_add(amount)
+            .ok_or(BankError::MathOverflow)?;
+        Ok(())
+    }
+}
+
+// 从 payer 转入 bank PDA 并记入 user_account，先结算利息再检查限额
+fn take_deposit<'info>(
+    bank: &UncheckedAccount<'info>,
+    state: &mut BankState,
+    user_account: &mut Account<'info, UserAccount>,
+    payer: AccountInfo<'info>,
+    amount: u64,
+) -> Result<()> {
+    if is_new_account(&user_account.to_account_info())? {
+        state.user_count = state
+            .user_count
+            .checked_add(1)
+            .ok_or(BankError::MathOverflow)?;
+    }
+    state.settle_interest(user_account, Clock::get()?.slot)?;
+
+    let new_balance = user_account
+        .deposit_amount
+        .checked_add(amount)
+        .ok_or(BankError::MathOverflow)?;
+    state.record_deposit(new_balance, amount)?;
+
+    // 使用 system_program::transfer 从用户转账到 bank PDA
+    // System Program 检测到目标账户不存在，自动创建：
+    // - Owner: System Program (11111...1111)
+    // - Lamports: amount
+    // - Data: []
+    system_program::transfer(
+        CpiContext::new(
+            system_program::ID,
+            system_program::Transfer {
+                from: payer,
+                to: bank.to_account_info(),
+            },
+        ),
+        amount,
+    )?;
+
+    // 更新用户存款记录
+    user_account.deposit_amount = new_balance;
+    Ok(())
+}
+
+// init_if_needed 刚创建的账户在指令结束前还没有写入 discriminator
+fn is_new_account(info: &AccountInfo) -> Result<bool> {
+    Ok(info.try_borrow_data()?[..ANCHOR_DISCRIMINATOR_SIZE]
+        .iter()
+        .all(|byte| *byte == 0))
+}
+
+// 从 bank PDA 向 to 转出 lamports，保留 reserve 并计入 epoch 提取额度
+fn pay_out<'info>(
+    bank: &UncheckedAccount<'info>,
+    bank_bump: u8,
+    state: &mut BankState,
+    to: AccountInfo<'info>,
+    amount: u64,
+) -> Result<()> {
+    // 确保 bank 账户扣除 reserve 后有足够的余额
+    let available = bank.lamports().saturating_sub(bank_reserve()?);
+    require!(available >= amount, BankError::InsufficientBankFunds);
+
+    state.record_withdrawal(amount, Clock::get()?.epoch)?;
+
+    // 使用 system_program::transfer 配合 PDA seeds 签名进行转账
+    // 这是推荐的做法
+    let seeds = &[b"bank".as_ref(), &[bank_bump]];
+    let signer_seeds = &[&seeds[..]];
+
+    system_program::transfer(
+        CpiContext::new_with_signer(
+            system_program::ID,
+            system_program::Transfer {
+                from: bank.to_account_info(),
+                to,
+            },
+            signer_seeds,
+        ),
+        amount,
+    )
+}
+
+#[derive(Accounts)]
+pub struct Initialize<'info> {
+    /// CHECK: Bank 是一个空的 PDA，由 System Program 拥有，仅用于存储 SOL
+    #[account(
+        mut,
+        seeds = [b"bank"],
+        bump
+    )]
+    pub bank: UncheckedAccount<'info>,
+    #[account(
+        init,
+        payer = admin,
+        space = ANCHOR_DISCRIMINATOR_SIZE + BankState::INIT_SPACE,
+        seeds = [b"state"],
+        bump
+    )]
+    pub state: Account<'info, BankState>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CheckSolvency<'info> {
+    /// CHECK: Bank 是一个空的 PDA，由 System Program 拥有，仅用于存储 SOL
+    #[account(
+        seeds = [b"bank"],
+        bump
+    )]
+    pub bank: UncheckedAccount<'info>,
+    #[account(
+        seeds = [b"state"],
+        bump
+    )]
+    pub state: Account<'info, BankState>,
+}
+
+#[derive(Accounts)]
+pub struct AdminOnly<'info> {
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump,
+        has_one = admin @ BankError::Unauthorized
+    )]
+    pub state: Account<'info, BankState>,
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptAdmin<'info> {
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump
+    )]
+    pub state: Account<'info, BankState>,
+    pub new_admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct FundInterest<'info> {
+    /// CHECK: Bank 是一个空的 PDA，由 System Program 拥有，仅用于存储 SOL
+    #[account(
+        mut,
+        seeds = [b"bank"],
+        bump
+    )]
+    pub bank: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump,
+        has_one = admin @ BankError::Unauthorized
+    )]
+    pub state: Account<'info, BankState>,
+    pub admin: Signer<'info>,
+    // 利息由 payer 转入，admin 可以是不能转出 lamports 的多签 PDA
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
@@ -90,11 +596,37 @@ pub struct CreateUserAccount<'info> {
     #[account(
         init,
         payer = owner,
-        space = 8 + 8, // discriminator + u64
+        space = ANCHOR_DISCRIMINATOR_SIZE + UserAccount::INIT_SPACE,
         seeds = [b"user", owner.key().as_ref()],
         bump
     )]
     pub user_account: Account<'info, UserAccount>,
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump
+    )]
+    pub state: Account<'info, BankState>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateUserAccount<'info> {
+    /// CHECK: 旧版 UserAccount（discriminator + deposit_amount），在指令中校验 owner 和数据格式
+    #[account(
+        mut,
+        seeds = [b"user", owner.key().as_ref()],
+        bump
+    )]
+    pub user_account: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump
+    )]
+    pub state: Account<'info, BankState>,
     #[account(mut)]
     pub owner: Signer<'info>,
     pub system_program: Program<'info, System>,
@@ -112,6 +644,16 @@ pub struct Deposit<'info> {
 
     #[account(
         mut,
+        seeds = [b"state"],
+        bump
+    )]
+    pub state: Account<'info, BankState>,
+
+    // 首次存款时创建，已有账户只会被反序列化，不会被重新初始化
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = ANCHOR_DISCRIMINATOR_SIZE + UserAccount::INIT_SPACE,
         seeds = [b"user", depositor.key().as_ref()],
         bump
     )]
@@ -120,6 +662,43 @@ pub struct Deposit<'info> {
     #[account(mut)]
     pub depositor: Signer<'info>,
 
+    // 支付 UserAccount 租金，可以与 depositor 相同
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(beneficiary: Pubkey)]
+pub struct DepositFor<'info> {
+    /// CHECK: Bank 是一个空的 PDA，由 System Program 拥有，仅用于存储 SOL
+    #[account(
+        mut,
+        seeds = [b"bank"],
+        bump
+    )]
+    pub bank: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump
+    )]
+    pub state: Account<'info, BankState>,
+
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = ANCHOR_DISCRIMINATOR_SIZE + UserAccount::INIT_SPACE,
+        seeds = [b"user", beneficiary.as_ref()],
+        bump
+    )]
+    pub user_account: Account<'info, UserAccount>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
     pub system_program: Program<'info, System>,
 }
 
@@ -133,6 +712,13 @@ pub struct Withdraw<'info> {
     )]
     pub bank: UncheckedAccount<'info>,
 
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump
+    )]
+    pub state: Account<'info, BankState>,
+
     #[account(
         mut,
         seeds = [b"user", receiver.key().as_ref()],
@@ -144,9 +730,462 @@ pub struct Withdraw<'info> {
     pub system_program: Program<'info, System>,
 }
 
+#[derive(Accounts)]
+pub struct WithdrawTo<'info> {
+    /// CHECK: Bank 是一个空的 PDA，由 System Program 拥有，仅用于存储 SOL
+    #[account(
+        mut,
+        seeds = [b"bank"],
+        bump
+    )]
+    pub bank: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump
+    )]
+    pub state: Account<'info, BankState>,
+
+    #[account(
+        mut,
+        seeds = [b"user", owner.key().as_ref()],
+        bump
+    )]
+    pub user_account: Account<'info, UserAccount>,
+    pub owner: Signer<'info>,
+    #[account(mut)]
+    pub recipient: SystemAccount<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(to: Pubkey)]
+pub struct TransferInternal<'info> {
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump
+    )]
+    pub state: Account<'info, BankState>,
+    #[account(
+        mut,
+        seeds = [b"user", owner.key().as_ref()],
+        bump
+    )]
+    pub user_account: Account<'info, UserAccount>,
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = ANCHOR_DISCRIMINATOR_SIZE + UserAccount::INIT_SPACE,
+        seeds = [b"user", to.as_ref()],
+        bump
+    )]
+    pub to_user_account: Account<'info, UserAccount>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CloseUserAccount<'info> {
+    #[account(
+        mut,
+        close = owner,
+        seeds = [b"user", owner.key().as_ref()],
+        bump
+    )]
+    pub user_account: Account<'info, UserAccount>,
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump
+    )]
+    pub state: Account<'info, BankState>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawAllAndClose<'info> {
+    /// CHECK: Bank 是一个空的 PDA，由 System Program 拥有，仅用于存储 SOL
+    #[account(
+        mut,
+        seeds = [b"bank"],
+        bump
+    )]
+    pub bank: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump
+    )]
+    pub state: Account<'info, BankState>,
+    #[account(
+        mut,
+        close = owner,
+        seeds = [b"user", owner.key().as_ref()],
+        bump
+    )]
+    pub user_account: Account<'info, UserAccount>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(request_id: u64)]
+pub struct RequestWithdraw<'info> {
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump
+    )]
+    pub state: Account<'info, BankState>,
+    #[account(
+        mut,
+        seeds = [b"user", owner.key().as_ref()],
+        bump
+    )]
+    pub user_account: Account<'info, UserAccount>,
+    #[account(
+        init,
+        payer = owner,
+        space = ANCHOR_DISCRIMINATOR_SIZE + PendingWithdrawal::INIT_SPACE,
+        seeds = [b"pending", owner.key().as_ref(), &request_id.to_le_bytes()],
+        bump
+    )]
+    pub pending_withdrawal: Account<'info, PendingWithdrawal>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(request_id: u64)]
+pub struct ExecuteWithdraw<'info> {
+    /// CHECK: Bank 是一个空的 PDA，由 System Program 拥有，仅用于存储 SOL
+    #[account(
+        mut,
+        seeds = [b"bank"],
+        bump
+    )]
+    pub bank: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump
+    )]
+    pub state: Account<'info, BankState>,
+    #[account(
+        mut,
+        close = owner,
+        seeds = [b"pending", owner.key().as_ref(), &request_id.to_le_bytes()],
+        bump,
+        has_one = owner
+    )]
+    pub pending_withdrawal: Account<'info, PendingWithdrawal>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(request_id: u64)]
+pub struct CancelWithdraw<'info> {
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump
+    )]
+    pub state: Account<'info, BankState>,
+    #[account(
+        mut,
+        seeds = [b"user", owner.key().as_ref()],
+        bump
+    )]
+    pub user_account: Account<'info, UserAccount>,
+    #[account(
+        mut,
+        close = owner,
+        seeds = [b"pending", owner.key().as_ref(), &request_id.to_le_bytes()],
+        bump,
+        has_one = owner
+    )]
+    pub pending_withdrawal: Account<'info, PendingWithdrawal>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+}
+
 #[account]
+#[derive(InitSpace)]
+pub struct PendingWithdrawal {
+    pub owner: Pubkey,
+    pub request_id: u64,
+    pub amount: u64,
+    pub unlock_ts: i64,
+}
+
+#[account]
+#[derive(InitSpace)]
 pub struct UserAccount {
     pub deposit_amount: u64,
+    // 上次结算利息时的全局指数
+    pub interest_index: u128,
+    // 当前窗口内累计的即时提款，窗口长度为 withdraw_delay
+    pub instant_window_start: i64,
+    pub instant_withdrawn: u64,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct BankState {
+    pub admin: Pubkey,
+    pub pending_admin: Option<Pubkey>,
+    // 所有 UserAccount 记录的存款之和
+    pub total_deposits: u64,
+    // 已创建的 UserAccount 数量
+    pub user_count: u64,
+    pub created_slot: u64,
+    // 以下限额为 0 表示不限制
+    pub tvl_cap: u64,
+    pub max_user_balance: u64,
+    pub epoch_withdraw_limit: u64,
+    // 当前统计的 epoch 及其已提取的总额
+    pub withdraw_epoch: u64,
+    pub epoch_withdrawn: u64,
+    // 年化利率（bps），按经过的 slot 线性累计到 interest_index
+    pub interest_rate_bps: u16,
+    pub interest_index: u128,
+    pub last_accrual_slot: u64,
+    // admin 注入、尚未支付的利息
+    pub interest_pool: u64,
+    // 达到该金额的提款需走延迟流程，0 表示不启用
+    pub large_withdraw_threshold: u64,
+    pub withdraw_delay: i64,
+    // 已从用户余额扣除、等待执行的提款之和
+    pub pending_withdrawals: u64,
+}
+
+pub fn legacy_deposit_amount(data: &[u8]) -> Result<u64> {
+    require!(
+        data.len() == LEGACY_USER_ACCOUNT_SIZE && data.starts_with(UserAccount::DISCRIMINATOR),
+        BankError::InvalidLegacyAccount
+    );
+    Ok(u64::from_le_bytes(
+        data[ANCHOR_DISCRIMINATOR_SIZE..].try_into().unwrap(),
+    ))
+}
+
+// bank PDA 没有数据，reserve 即 0 字节账户的免租金最低余额
+fn bank_reserve() -> Result<u64> {
+    Ok(Rent::get()?.minimum_balance(0))
+}
+
+impl BankState {
+    pub fn set_withdraw_delay(
+        &mut self,
+        large_withdraw_threshold: u64,
+        withdraw_delay: i64,
+    ) -> Result<()> {
+        require!(
+            (0..=MAX_WITHDRAW_DELAY).contains(&withdraw_delay),
+            BankError::InvalidWithdrawDelay
+        );
+        self.large_withdraw_threshold = large_withdraw_threshold;
+        self.withdraw_delay = withdraw_delay;
+        Ok(())
+    }
+
+    pub fn check_solvency(&self, bank_lamports: u64, reserve: u64) -> Result<()> {
+        let liabilities = self
+            .total_deposits
+            .checked_add(self.interest_pool)
+            .and_then(|total| total.checked_add(self.pending_withdrawals))
+            .ok_or(BankError::MathOverflow)?;
+        // 未迁移的旧存款或直接转入的 lamports 形成盈余，仍视为偿付
+        let available = bank_lamports.saturating_sub(reserve);
+        require!(available >= liabilities, BankError::AccountingMismatch);
+        Ok(())
+    }
+
+    // new_balance 为存入后用户的余额
+    pub fn record_deposit(&mut self, new_balance: u64, amount: u64) -> Result<()> {
+        let total_deposits = self
+            .total_deposits
+            .checked_add(amount)
+            .ok_or(BankError::MathOverflow)?;
+        require!(
+            self.tvl_cap == 0 || total_deposits <= self.tvl_cap,
+            BankError::DepositCapExceeded
+        );
+        self.check_user_balance(new_balance)?;
+        self.total_deposits = total_deposits;
+        Ok(())
+    }
+
+    // 旧账本的 lamports 在升级前就已存入 bank PDA，迁移时计入存款总额和用户数，不受限额约束
+    pub fn record_migration(&mut self, amount: u64) -> Result<()> {
+        self.total_deposits = self
+            .total_deposits
+            .checked_add(amount)
+            .ok_or(BankError::MathOverflow)?;
+        self.user_count = self
+            .user_count
+            .checked_add(1)
+            .ok_or(BankError::MathOverflow)?;
+        Ok(())
+    }
+
+    pub fn check_user_balance(&self, new_balance: u64) -> Result<()> {
+        require!(
+            self.max_user_balance == 0 || new_balance <= self.max_user_balance,
+            BankError::UserBalanceCapExceeded
+        );
+        Ok(())
+    }
+
+    pub fn record_withdrawal(&mut self, amount: u64, epoch: u64) -> Result<()> {
+        // 进入新的 epoch 时重新计数
+        if epoch != self.withdraw_epoch {
+            self.withdraw_epoch = epoch;
+            self.epoch_withdrawn = 0;
+        }
+        let epoch_withdrawn = self
+            .epoch_withdrawn
+            .checked_add(amount)
+            .ok_or(BankError::MathOverflow)?;
+        require!(
+            self.epoch_withdraw_limit == 0 || epoch_withdrawn <= self.epoch_withdraw_limit,
+            BankError::EpochWithdrawLimitExceeded
+        );
+        self.epoch_withdrawn = epoch_withdrawn;
+        self.total_deposits = self
+            .total_deposits
+            .checked_sub(amount)
+            .ok_or(BankError::MathOverflow)?;
+        Ok(())
+    }
+
+    pub fn accrue_interest(&mut self, slot: u64) -> Result<()> {
+        let elapsed = slot.saturating_sub(self.last_accrual_slot);
+        if elapsed == 0 {
+            return Ok(());
+        }
+        let delta = INTEREST_INDEX_PRECISION
+            .checked_mul(self.interest_rate_bps as u128 * elapsed as u128)
+            .ok_or(BankError::MathOverflow)?
+            / (10_000 * SLOTS_PER_YEAR as u128);
+        self.interest_index = self
+            .interest_index
+            .checked_add(delta)
+            .ok_or(BankError::MathOverflow)?;
+        self.last_accrual_slot = slot;
+        Ok(())
+    }
+
+    // 把上次结算以来的利息计入用户余额，最多支付利息池中剩余的部分
+    pub fn settle_interest(&mut self, user: &mut UserAccount, slot: u64) -> Result<u64> {
+        self.accrue_interest(slot)?;
+        let owed = (user.deposit_amount as u128)
+            .checked_mul(self.interest_index.saturating_sub(user.interest_index))
+            .ok_or(BankError::MathOverflow)?
+            / INTEREST_INDEX_PRECISION;
+        let paid = owed.min(self.interest_pool as u128) as u64;
+
+        self.interest_pool -= paid;
+        self.total_deposits = self
+            .total_deposits
+            .checked_add(paid)
+            .ok_or(BankError::MathOverflow)?;
+        user.deposit_amount = user
+            .deposit_amount
+            .checked_add(paid)
+            .ok_or(BankError::MathOverflow)?;
+        user.interest_index = self.interest_index;
+        Ok(paid)
+    }
+
+    // 按用户累计 withdraw_delay 窗口内的即时提款，拆成多笔小额提款也不能绕过延迟
+    pub fn record_instant_withdrawal(
+        &self,
+        user: &mut UserAccount,
+        amount: u64,
+        now: i64,
+    ) -> Result<()> {
+        if self.large_withdraw_threshold == 0 {
+            return Ok(());
+        }
+        let window_end = user
+            .instant_window_start
+            .checked_add(self.withdraw_delay)
+            .ok_or(BankError::MathOverflow)?;
+        if now >= window_end {
+            user.instant_window_start = now;
+            user.instant_withdrawn = 0;
+        }
+        let withdrawn = user
+            .instant_withdrawn
+            .checked_add(amount)
+            .ok_or(BankError::MathOverflow)?;
+        require!(
+            withdrawn < self.large_withdraw_threshold,
+            BankError::WithdrawalRequiresDelay
+        );
+        user.instant_withdrawn = withdrawn;
+        Ok(())
+    }
+
+    // 把 amount 从存款总额移到待执行提款，避免同一笔余额被重复提取
+    pub fn reserve_withdrawal(&mut self, amount: u64) -> Result<()> {
+        self.total_deposits = self
+            .total_deposits
+            .checked_sub(amount)
+            .ok_or(BankError::MathOverflow)?;
+        self.pending_withdrawals = self
+            .pending_withdrawals
+            .checked_add(amount)
+            .ok_or(BankError::MathOverflow)?;
+        Ok(())
+    }
+
+    pub fn release_withdrawal(&mut self, amount: u64) -> Result<()> {
+        self.pending_withdrawals = self
+            .pending_withdrawals
+            .checked_sub(amount)
+            .ok_or(BankError::MathOverflow)?;
+        self.total_deposits = self
+            .total_deposits
+            .checked_add(amount)
+            .ok_or(BankError::MathOverflow)?;
+        Ok(())
+    }
+
+    pub fn accept_admin(&mut self, new_admin: Pubkey) -> Result<()> {
+        require!(
+            self.pending_admin == Some(new_admin),
+            BankError::InvalidPendingAdmin
+        );
+
+        self.admin = new_admin;
+        self.pending_admin = None;
+        Ok(())
+    }
+
+    pub fn remove_user(&mut self) -> Result<()> {
+        self.user_count = self
+            .user_count
+            .checked_sub(1)
+            .ok_or(BankError::MathOverflow)?;
+        Ok(())
+    }
+}
+
+#[event]
+pub struct DepositForEvent {
+    pub payer: Pubkey,
+    pub beneficiary: Pubkey,
+    pub amount: u64,
 }
 
 #[error_code]
@@ -155,15 +1194,54 @@ pub enum BankError {
     InsufficientFunds,
     #[msg("银行资金不足")]
     InsufficientBankFunds,
+    #[msg("无权限")]
+    Unauthorized,
+    #[msg("超过存款总额上限")]
+    DepositCapExceeded,
+    #[msg("超过单个用户余额上限")]
+    UserBalanceCapExceeded,
+    #[msg("超过本 epoch 的提取额度")]
+    EpochWithdrawLimitExceeded,
+    #[msg("数值溢出")]
+    MathOverflow,
+    #[msg("bank 余额不足以覆盖存款记录")]
+    AccountingMismatch,
+    #[msg("账户余额不为零")]
+    AccountNotEmpty,
+    #[msg("不能转账给自己")]
+    SelfTransfer,
+    #[msg("利率超出上限")]
+    InvalidInterestRate,
+    #[msg("不是有效的旧版用户账户")]
+    InvalidLegacyAccount,
+    #[msg("金额必须大于 0")]
+    InvalidAmount,
+    #[msg("提款延迟必须在 0 到 MAX_WITHDRAW_DELAY 之间")]
+    InvalidWithdrawDelay,
+    #[msg("大额提款需要先申请并等待延迟")]
+    WithdrawalRequiresDelay,
+    #[msg("提款尚未解锁")]
+    WithdrawalLocked,
+    #[msg("签名者不是待接受的 admin")]
+    InvalidPendingAdmin,
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn user_account(deposit_amount: u64) -> UserAccount {
+        UserAccount {
+            deposit_amount,
+            interest_index: INTEREST_INDEX_PRECISION,
+            instant_window_start: 0,
+            instant_withdrawn: 0,
+        }
+    }
+
     #[test]
     fn user_account_tracks_deposit_amount() {
-        let mut account = UserAccount { deposit_amount: 0 };
+        let mut account = user_account(0);
 
         account.deposit_amount = account.deposit_amount.checked_add(1_000).unwrap();
         account.deposit_amount = account.deposit_amount.checked_add(250).unwrap();
@@ -173,9 +1251,7 @@ mod tests {
 
     #[test]
     fn user_account_withdraw_reduces_balance() {
-        let mut account = UserAccount {
-            deposit_amount: 2_000,
-        };
+        let mut account = user_account(2_000);
 
         account.deposit_amount = account.deposit_amount.checked_sub(750).unwrap();
 
@@ -184,13 +1260,275 @@ mod tests {
 
     #[test]
     fn user_account_cannot_underflow() {
-        let account = UserAccount { deposit_amount: 500 };
+        let account = user_account(500);
 
         let result = account.deposit_amount.checked_sub(750);
 
         assert!(result.is_none());
     }
 
+    fn state(tvl_cap: u64, max_user_balance: u64, epoch_withdraw_limit: u64) -> BankState {
+        BankState {
+            admin: Pubkey::new_unique(),
+            pending_admin: None,
+            total_deposits: 0,
+            user_count: 0,
+            created_slot: 0,
+            tvl_cap,
+            max_user_balance,
+            epoch_withdraw_limit,
+            withdraw_epoch: 0,
+            epoch_withdrawn: 0,
+            interest_rate_bps: 0,
+            interest_index: INTEREST_INDEX_PRECISION,
+            last_accrual_slot: 0,
+            interest_pool: 0,
+            large_withdraw_threshold: 0,
+            withdraw_delay: 0,
+            pending_withdrawals: 0,
+        }
+    }
+
+    #[test]
+    fn deposits_respect_tvl_and_user_caps() {
+        let mut state = state(1_000, 600, 0);
+
+        state.record_deposit(600, 600).unwrap();
+        assert!(state.record_deposit(601, 1).is_err());
+        state.record_deposit(400, 400).unwrap();
+        assert!(state.record_deposit(1, 1).is_err());
+        assert_eq!(state.total_deposits, 1_000);
+    }
+
+    #[test]
+    fn zero_limits_mean_unlimited() {
+        let mut state = state(0, 0, 0);
+
+        state.record_deposit(u64::MAX / 2, u64::MAX / 2).unwrap();
+        state.record_withdrawal(u64::MAX / 2, 1).unwrap();
+        assert_eq!(state.total_deposits, 0);
+    }
+
+    #[test]
+    fn epoch_withdraw_limit_resets_each_epoch() {
+        let mut state = state(0, 0, 500);
+        state.record_deposit(2_000, 2_000).unwrap();
+
+        state.record_withdrawal(300, 7).unwrap();
+        assert!(state.record_withdrawal(201, 7).is_err());
+        state.record_withdrawal(200, 7).unwrap();
+        state.record_withdrawal(500, 8).unwrap();
+        assert_eq!(state.total_deposits, 1_000);
+    }
+
+    #[test]
+    fn solvency_excludes_rent_reserve() {
+        let mut state = state(0, 0, 0);
+        state.record_deposit(1_000, 1_000).unwrap();
+
+        state.check_solvency(1_000 + 890_880, 890_880).unwrap();
+        assert!(state.check_solvency(1_000, 890_880).is_err());
+        assert!(state.check_solvency(999 + 890_880, 890_880).is_err());
+    }
+
+    #[test]
+    fn solvency_allows_surplus() {
+        let mut state = state(0, 0, 0);
+        state.record_deposit(1_000, 1_000).unwrap();
+
+        state.check_solvency(2_000 + 890_880, 890_880).unwrap();
+    }
+
+    #[test]
+    fn solvency_includes_interest_pool() {
+        let mut state = state(0, 0, 0);
+        state.record_deposit(1_000, 1_000).unwrap();
+        state.interest_pool = 500;
+
+        state.check_solvency(1_500 + 890_880, 890_880).unwrap();
+        assert!(state.check_solvency(1_000 + 890_880, 890_880).is_err());
+    }
+
+    #[test]
+    fn interest_accrues_at_configured_apr() {
+        let mut state = state(0, 0, 0);
+        state.interest_rate_bps = 500;
+        state.interest_pool = 1_000_000;
+        state.record_deposit(1_000_000, 1_000_000).unwrap();
+        let mut alice = user_account(1_000_000);
+
+        // 一年 5%
+        let paid = state.settle_interest(&mut alice, SLOTS_PER_YEAR).unwrap();
+        assert_eq!(paid, 50_000);
+        assert_eq!(alice.deposit_amount, 1_050_000);
+        assert_eq!(alice.interest_index, state.interest_index);
+        assert_eq!(state.total_deposits, 1_050_000);
+        assert_eq!(state.interest_pool, 950_000);
+
+        // 同一 slot 再次结算不会重复支付
+        assert_eq!(state.settle_interest(&mut alice, SLOTS_PER_YEAR).unwrap(), 0);
+    }
+
+    #[test]
+    fn interest_is_capped_by_pool() {
+        let mut state = state(0, 0, 0);
+        state.interest_rate_bps = 10_000;
+        state.interest_pool = 300;
+        state.record_deposit(1_000, 1_000).unwrap();
+        let mut alice = user_account(1_000);
+
+        let paid = state.settle_interest(&mut alice, SLOTS_PER_YEAR).unwrap();
+        assert_eq!(paid, 300);
+        assert_eq!(state.interest_pool, 0);
+        assert_eq!(alice.deposit_amount, 1_300);
+    }
+
+    #[test]
+    fn new_user_does_not_earn_past_interest() {
+        let mut state = state(0, 0, 0);
+        state.interest_rate_bps = 1_000;
+        state.interest_pool = 1_000_000;
+        state.accrue_interest(SLOTS_PER_YEAR).unwrap();
+
+        let mut bob = user_account(0);
+        bob.interest_index = state.interest_index;
+        bob.deposit_amount = 1_000;
+        assert_eq!(state.settle_interest(&mut bob, SLOTS_PER_YEAR).unwrap(), 0);
+    }
+
+    #[test]
+    fn legacy_user_account_is_parsed() {
+        let mut data = UserAccount::DISCRIMINATOR.to_vec();
+        data.extend_from_slice(&1_234u64.to_le_bytes());
+
+        assert_eq!(legacy_deposit_amount(&data).unwrap(), 1_234);
+        assert!(legacy_deposit_amount(&data[..12]).is_err());
+        data[0] ^= 1;
+        assert!(legacy_deposit_amount(&data).is_err());
+    }
+
+    #[test]
+    fn large_withdrawals_require_delay() {
+        let mut state = state(0, 0, 0);
+        let mut user = user_account(0);
+        state
+            .record_instant_withdrawal(&mut user, u64::MAX, 0)
+            .unwrap();
+
+        state.large_withdraw_threshold = 1_000;
+        state.withdraw_delay = 100;
+        let mut user = user_account(0);
+        state.record_instant_withdrawal(&mut user, 999, 0).unwrap();
+        assert!(state.record_instant_withdrawal(&mut user, 1_000, 0).is_err());
+    }
+
+    #[test]
+    fn withdraw_delay_is_bounded() {
+        let mut state = state(0, 0, 0);
+        state.set_withdraw_delay(1_000, MAX_WITHDRAW_DELAY).unwrap();
+        assert!(state.set_withdraw_delay(1_000, MAX_WITHDRAW_DELAY + 1).is_err());
+        assert!(state.set_withdraw_delay(1_000, -1).is_err());
+        assert!(state.set_withdraw_delay(1_000, i64::MAX).is_err());
+        assert_eq!(state.large_withdraw_threshold, 1_000);
+        assert_eq!(state.withdraw_delay, MAX_WITHDRAW_DELAY);
+    }
+
+    #[test]
+    fn instant_withdrawals_accumulate_within_window() {
+        let mut state = state(0, 0, 0);
+        state.large_withdraw_threshold = 1_000;
+        state.withdraw_delay = 100;
+        let mut user = user_account(0);
+
+        // 拆分的小额提款在同一窗口内累计
+        state.record_instant_withdrawal(&mut user, 600, 1_000).unwrap();
+        assert!(state.record_instant_withdrawal(&mut user, 400, 1_050).is_err());
+        state.record_instant_withdrawal(&mut user, 399, 1_050).unwrap();
+        assert_eq!(user.instant_withdrawn, 999);
+
+        // 窗口结束后重新计算
+        assert!(state.record_instant_withdrawal(&mut user, 1, 1_099).is_err());
+        state.record_instant_withdrawal(&mut user, 600, 1_100).unwrap();
+        assert_eq!(user.instant_window_start, 1_100);
+        assert_eq!(user.instant_withdrawn, 600);
+    }
+
+    #[test]
+    fn pending_withdrawals_stay_in_solvency() {
+        let mut state = state(0, 0, 0);
+        state.record_deposit(1_000, 1_000).unwrap();
+
+        state.reserve_withdrawal(600).unwrap();
+        assert_eq!(state.total_deposits, 400);
+        state.check_solvency(1_000 + 890_880, 890_880).unwrap();
+        assert!(state.reserve_withdrawal(401).is_err());
+
+        // 执行时先释放再按普通提款记账
+        state.release_withdrawal(600).unwrap();
+        state.record_withdrawal(600, 0).unwrap();
+        assert_eq!(state.pending_withdrawals, 0);
+        state.check_solvency(400 + 890_880, 890_880).unwrap();
+    }
+
+    #[test]
+    fn only_pending_admin_can_accept() {
+        let mut state = state(0, 0, 0);
+        let multisig = Pubkey::new_unique();
+
+        assert!(state.accept_admin(multisig).is_err());
+        state.pending_admin = Some(multisig);
+        assert!(state.accept_admin(Pubkey::new_unique()).is_err());
+        state.accept_admin(multisig).unwrap();
+        assert_eq!(state.admin, multisig);
+        assert_eq!(state.pending_admin, None);
+    }
+
+    #[test]
+    fn migrated_balance_can_be_withdrawn() {
+        // 升级前的存款不在 total_deposits 中，迁移后才能提取
+        let mut state = state(500, 0, 0);
+        assert!(state.record_withdrawal(400, 0).is_err());
+
+        // 即使超过 tvl_cap 也要计入
+        state.record_migration(1_000).unwrap();
+        state.record_withdrawal(400, 0).unwrap();
+        assert_eq!(state.total_deposits, 600);
+    }
+
+    #[test]
+    fn migrated_user_can_be_removed() {
+        let mut state = state(0, 0, 0);
+        assert!(state.remove_user().is_err());
+
+        state.record_migration(0).unwrap();
+        assert_eq!(state.user_count, 1);
+        state.remove_user().unwrap();
+        assert_eq!(state.user_count, 0);
+    }
+
+    #[test]
+    fn remove_user_rejects_underflow() {
+        let mut state = state(0, 0, 0);
+        state.user_count = 1;
+
+        state.remove_user().unwrap();
+        assert_eq!(state.user_count, 0);
+        assert!(state.remove_user().is_err());
+    }
+
+    #[test]
+    fn new_account_is_detected_by_zeroed_discriminator() {
+        let key = Pubkey::new_unique();
+        let owner = ID;
+        let mut lamports = 0;
+        let mut data = vec![0u8; 16];
+        let info = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &owner, false);
+        assert!(is_new_account(&info).unwrap());
+
+        info.try_borrow_mut_data().unwrap()[..8].copy_from_slice(UserAccount::DISCRIMINATOR);
+        assert!(!is_new_account(&info).unwrap());
+    }
+
     #[test]
     fn bank_pda_is_stable() {
         let (bank_pda, _) = Pubkey::find_program_address(&[b"bank"], &ID);
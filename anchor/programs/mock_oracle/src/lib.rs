@@ -0,0 +1,124 @@
+use anchor_lang::prelude::*;
+
+declare_id!("CtpEHgxf1J5WRYC63GPRAvz9XH69Tn6SBy6NK8hctyVf");
+
+pub const ANCHOR_DISCRIMINATOR_SIZE: usize = 8;
+
+// 本地测试用的简易价格预言机：价格由 authority 直接写入
+// 价格账户按 (authority, mint) 派生，使用方需校验信任的 authority
+#[program]
+pub mod mock_oracle {
+    use super::*;
+
+    pub fn initialize_feed(
+        ctx: Context<InitializeFeed>,
+        price: i64,
+        expo: i32,
+        max_staleness: i64,
+    ) -> Result<()> {
+        require!(max_staleness > 0, OracleError::InvalidStaleness);
+        ctx.accounts.price_feed.set_inner(PriceFeed {
+            authority: ctx.accounts.authority.key(),
+            mint: ctx.accounts.mint.key(),
+            price,
+            expo,
+            publish_time: Clock::get()?.unix_timestamp,
+            max_staleness,
+        });
+        Ok(())
+    }
+
+    // 更新价格，发布时间取当前链上时间
+    pub fn set_price(ctx: Context<SetPrice>, price: i64, expo: i32) -> Result<()> {
+        let price_feed = &mut ctx.accounts.price_feed;
+        price_feed.price = price;
+        price_feed.expo = expo;
+        price_feed.publish_time = Clock::get()?.unix_timestamp;
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct InitializeFeed<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = ANCHOR_DISCRIMINATOR_SIZE + PriceFeed::INIT_SPACE,
+        seeds = [b"price", authority.key().as_ref(), mint.key().as_ref()],
+        bump
+    )]
+    pub price_feed: Account<'info, PriceFeed>,
+    /// CHECK: 只用作派生价格账户的 seed
+    pub mint: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetPrice<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"price",
+            price_feed.authority.as_ref(),
+            price_feed.mint.as_ref()
+        ],
+        bump,
+        has_one = authority @ OracleError::Unauthorized
+    )]
+    pub price_feed: Account<'info, PriceFeed>,
+    pub authority: Signer<'info>,
+}
+
+// 实际价格 = price * 10^expo
+#[account]
+#[derive(InitSpace)]
+pub struct PriceFeed {
+    pub authority: Pubkey,
+    pub mint: Pubkey,
+    pub price: i64,
+    pub expo: i32,
+    pub publish_time: i64,
+    // 超过发布时间这么多秒后价格视为过期
+    pub max_staleness: i64,
+}
+
+impl PriceFeed {
+    pub fn is_stale(&self, now: i64) -> bool {
+        now.saturating_sub(self.publish_time) > self.max_staleness
+    }
+}
+
+#[error_code]
+pub enum OracleError {
+    #[msg("Unauthorized")]
+    Unauthorized,
+    #[msg("Staleness limit must be positive")]
+    InvalidStaleness,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn price_feed(publish_time: i64, max_staleness: i64) -> PriceFeed {
+        PriceFeed {
+            authority: Pubkey::new_unique(),
+            mint: Pubkey::new_unique(),
+            price: 150_000_000,
+            expo: -6,
+            publish_time,
+            max_staleness,
+        }
+    }
+
+    #[test]
+    fn price_is_fresh_within_staleness_limit() {
+        let feed = price_feed(100, 60);
+
+        assert!(!feed.is_stale(100));
+        assert!(!feed.is_stale(160));
+        assert!(feed.is_stale(161));
+    }
+}
@@ -0,0 +1,474 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
+use anchor_lang::solana_program::program::invoke_signed;
+
+declare_id!("ArSLo4Xm85oed1MEqdWGCxWE6MhVpHPNBsb56MND2Pcp");
+
+pub const ANCHOR_DISCRIMINATOR_SIZE: usize = 8;
+pub const MAX_MEMBERS: usize = 10;
+pub const MAX_PROPOSAL_ACCOUNTS: usize = 16;
+pub const MAX_PROPOSAL_DATA: usize = 512;
+
+// M-of-N 多签：Multisig PDA 本身作为 bank / tokenbank 的 admin
+// 成员提交任意指令作为提案，达到阈值后由程序用 PDA seeds 签名执行
+// Multisig PDA 存有数据，不能支付租金或转出 lamports，由无数据的 vault PDA 作为 payer
+#[program]
+pub mod multisig {
+    use super::*;
+
+    pub fn create_multisig(
+        ctx: Context<CreateMultisig>,
+        create_key: Pubkey,
+        members: Vec<Pubkey>,
+        threshold: u8,
+    ) -> Result<()> {
+        validate_members(&members, threshold)?;
+        let (_, vault_bump) = Pubkey::find_program_address(
+            &[b"vault", ctx.accounts.multisig.key().as_ref()],
+            &crate::ID,
+        );
+        ctx.accounts.multisig.set_inner(Multisig {
+            create_key,
+            members,
+            threshold,
+            proposal_count: 0,
+            bump: ctx.bumps.multisig,
+            vault_bump,
+        });
+        Ok(())
+    }
+
+    // 提交提案，提案人自动批准
+    pub fn propose(
+        ctx: Context<Propose>,
+        program_id: Pubkey,
+        accounts: Vec<ProposalAccount>,
+        data: Vec<u8>,
+        expires_at: i64,
+    ) -> Result<()> {
+        require!(
+            accounts.len() <= MAX_PROPOSAL_ACCOUNTS && data.len() <= MAX_PROPOSAL_DATA,
+            MultisigError::ProposalTooLarge
+        );
+        require!(
+            expires_at > Clock::get()?.unix_timestamp,
+            MultisigError::InvalidExpiry
+        );
+
+        let multisig = &mut ctx.accounts.multisig;
+        validate_signers(&accounts, &multisig.key(), &multisig.vault_address()?)?;
+        let member_index = multisig.member_index(&ctx.accounts.proposer.key())?;
+        let mut approvals = vec![false; multisig.members.len()];
+        approvals[member_index] = true;
+
+        ctx.accounts.proposal.set_inner(Proposal {
+            multisig: multisig.key(),
+            proposer: ctx.accounts.proposer.key(),
+            index: multisig.proposal_count,
+            program_id,
+            accounts,
+            data,
+            approvals,
+            expires_at,
+            executed: false,
+        });
+        multisig.proposal_count = multisig
+            .proposal_count
+            .checked_add(1)
+            .ok_or(MultisigError::MathOverflow)?;
+        Ok(())
+    }
+
+    pub fn approve(ctx: Context<Approve>) -> Result<()> {
+        let member_index = ctx
+            .accounts
+            .multisig
+            .member_index(&ctx.accounts.member.key())?;
+        ctx.accounts
+            .proposal
+            .approve(member_index, Clock::get()?.unix_timestamp)
+    }
+
+    // 达到阈值后任一成员都可以执行，提案涉及的账户（含目标程序）通过 remaining_accounts 传入
+    pub fn execute<'info>(ctx: Context<'info, Execute<'info>>) -> Result<()> {
+        let multisig = &ctx.accounts.multisig;
+        multisig.member_index(&ctx.accounts.member.key())?;
+
+        let proposal = &mut ctx.accounts.proposal;
+        proposal.check_executable(multisig.threshold, Clock::get()?.unix_timestamp)?;
+        let multisig_key = multisig.key();
+        validate_signers(
+            &proposal.accounts,
+            &multisig_key,
+            &multisig.vault_address()?,
+        )?;
+        proposal.executed = true;
+
+        let instruction = Instruction {
+            program_id: proposal.program_id,
+            accounts: proposal
+                .accounts
+                .iter()
+                .map(|account| AccountMeta {
+                    pubkey: account.pubkey,
+                    is_signer: account.is_signer,
+                    is_writable: account.is_writable,
+                })
+                .collect(),
+            data: proposal.data.clone(),
+        };
+        let seeds = &[
+            b"multisig".as_ref(),
+            multisig.create_key.as_ref(),
+            &[multisig.bump],
+        ];
+        let vault_seeds = &[
+            b"vault".as_ref(),
+            multisig_key.as_ref(),
+            &[multisig.vault_bump],
+        ];
+        invoke_signed(
+            &instruction,
+            ctx.remaining_accounts,
+            &[&seeds[..], &vault_seeds[..]],
+        )?;
+        Ok(())
+    }
+
+    // 已执行或已过期的提案可以关闭，租金退还给提案人
+    pub fn close_proposal(ctx: Context<CloseProposal>) -> Result<()> {
+        let proposal = &ctx.accounts.proposal;
+        require!(
+            proposal.executed || Clock::get()?.unix_timestamp >= proposal.expires_at,
+            MultisigError::ProposalStillActive
+        );
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+#[instruction(create_key: Pubkey, members: Vec<Pubkey>)]
+pub struct CreateMultisig<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = ANCHOR_DISCRIMINATOR_SIZE + Multisig::INIT_SPACE,
+        seeds = [b"multisig", create_key.as_ref()],
+        bump
+    )]
+    pub multisig: Account<'info, Multisig>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Propose<'info> {
+    #[account(
+        mut,
+        seeds = [b"multisig", multisig.create_key.as_ref()],
+        bump = multisig.bump
+    )]
+    pub multisig: Account<'info, Multisig>,
+    #[account(
+        init,
+        payer = proposer,
+        space = ANCHOR_DISCRIMINATOR_SIZE + Proposal::INIT_SPACE,
+        seeds = [
+            b"proposal",
+            multisig.key().as_ref(),
+            &multisig.proposal_count.to_le_bytes()
+        ],
+        bump
+    )]
+    pub proposal: Account<'info, Proposal>,
+    #[account(mut)]
+    pub proposer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Approve<'info> {
+    #[account(
+        seeds = [b"multisig", multisig.create_key.as_ref()],
+        bump = multisig.bump
+    )]
+    pub multisig: Account<'info, Multisig>,
+    #[account(mut, has_one = multisig)]
+    pub proposal: Account<'info, Proposal>,
+    pub member: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct Execute<'info> {
+    #[account(
+        seeds = [b"multisig", multisig.create_key.as_ref()],
+        bump = multisig.bump
+    )]
+    pub multisig: Account<'info, Multisig>,
+    #[account(mut, has_one = multisig)]
+    pub proposal: Account<'info, Proposal>,
+    pub member: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CloseProposal<'info> {
+    #[account(mut, close = proposer, has_one = proposer)]
+    pub proposal: Account<'info, Proposal>,
+    /// CHECK: 只接收退还的租金，由 has_one 校验
+    #[account(mut)]
+    pub proposer: UncheckedAccount<'info>,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Multisig {
+    // 用于派生 PDA，同一个程序可以有多个多签
+    pub create_key: Pubkey,
+    #[max_len(MAX_MEMBERS)]
+    pub members: Vec<Pubkey>,
+    pub threshold: u8,
+    pub proposal_count: u64,
+    pub bump: u8,
+    // vault PDA 由 System Program 拥有，只用于支付提案指令中的租金和转账
+    pub vault_bump: u8,
+}
+
+impl Multisig {
+    pub fn member_index(&self, key: &Pubkey) -> Result<usize> {
+        self.members
+            .iter()
+            .position(|member| member == key)
+            .ok_or_else(|| error!(MultisigError::NotAMember))
+    }
+
+    pub fn vault_address(&self) -> Result<Pubkey> {
+        let multisig = Pubkey::create_program_address(
+            &[b"multisig", self.create_key.as_ref(), &[self.bump]],
+            &crate::ID,
+        )
+        .map_err(|_| error!(MultisigError::InvalidVault))?;
+        Pubkey::create_program_address(
+            &[b"vault", multisig.as_ref(), &[self.vault_bump]],
+            &crate::ID,
+        )
+        .map_err(|_| error!(MultisigError::InvalidVault))
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, InitSpace)]
+pub struct ProposalAccount {
+    pub pubkey: Pubkey,
+    pub is_signer: bool,
+    pub is_writable: bool,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Proposal {
+    pub multisig: Pubkey,
+    pub proposer: Pubkey,
+    pub index: u64,
+    // 待执行的指令
+    pub program_id: Pubkey,
+    #[max_len(MAX_PROPOSAL_ACCOUNTS)]
+    pub accounts: Vec<ProposalAccount>,
+    #[max_len(MAX_PROPOSAL_DATA)]
+    pub data: Vec<u8>,
+    // 与 Multisig.members 一一对应
+    #[max_len(MAX_MEMBERS)]
+    pub approvals: Vec<bool>,
+    pub expires_at: i64,
+    pub executed: bool,
+}
+
+impl Proposal {
+    pub fn approve(&mut self, member_index: usize, now: i64) -> Result<()> {
+        self.check_active(now)?;
+        require!(
+            !self.approvals[member_index],
+            MultisigError::AlreadyApproved
+        );
+        self.approvals[member_index] = true;
+        Ok(())
+    }
+
+    pub fn check_executable(&self, threshold: u8, now: i64) -> Result<()> {
+        self.check_active(now)?;
+        let approvals = self.approvals.iter().filter(|approved| **approved).count();
+        require!(
+            approvals >= threshold as usize,
+            MultisigError::ThresholdNotMet
+        );
+        Ok(())
+    }
+
+    fn check_active(&self, now: i64) -> Result<()> {
+        require!(!self.executed, MultisigError::AlreadyExecuted);
+        require!(now < self.expires_at, MultisigError::ProposalExpired);
+        Ok(())
+    }
+}
+
+pub fn validate_members(members: &[Pubkey], threshold: u8) -> Result<()> {
+    require!(
+        !members.is_empty() && members.len() <= MAX_MEMBERS,
+        MultisigError::InvalidMembers
+    );
+    for (i, member) in members.iter().enumerate() {
+        require!(
+            !members[..i].contains(member),
+            MultisigError::DuplicateMember
+        );
+    }
+    require!(
+        threshold > 0 && threshold as usize <= members.len(),
+        MultisigError::InvalidThreshold
+    );
+    Ok(())
+}
+
+// 程序只能替多签 PDA 和 vault PDA 签名，其它签名者会沿用调用方交易中的签名，不能出现在提案里
+pub fn validate_signers(
+    accounts: &[ProposalAccount],
+    multisig: &Pubkey,
+    vault: &Pubkey,
+) -> Result<()> {
+    require!(
+        accounts.iter().all(|account| {
+            !account.is_signer || account.pubkey == *multisig || account.pubkey == *vault
+        }),
+        MultisigError::UnauthorizedSigner
+    );
+    Ok(())
+}
+
+#[error_code]
+pub enum MultisigError {
+    #[msg("Members must be between 1 and MAX_MEMBERS")]
+    InvalidMembers,
+    #[msg("Duplicate member")]
+    DuplicateMember,
+    #[msg("Threshold must be between 1 and the number of members")]
+    InvalidThreshold,
+    #[msg("Signer is not a member")]
+    NotAMember,
+    #[msg("Proposal instruction is too large")]
+    ProposalTooLarge,
+    #[msg("Expiry must be in the future")]
+    InvalidExpiry,
+    #[msg("Proposal has expired")]
+    ProposalExpired,
+    #[msg("Proposal has already been executed")]
+    AlreadyExecuted,
+    #[msg("Member has already approved")]
+    AlreadyApproved,
+    #[msg("Not enough approvals")]
+    ThresholdNotMet,
+    #[msg("Proposal is still active")]
+    ProposalStillActive,
+    #[msg("Only the multisig and its vault may sign a proposal instruction")]
+    UnauthorizedSigner,
+    #[msg("Invalid multisig vault")]
+    InvalidVault,
+    #[msg("Math overflow")]
+    MathOverflow,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn proposal(members: usize, expires_at: i64) -> Proposal {
+        Proposal {
+            multisig: Pubkey::new_unique(),
+            proposer: Pubkey::new_unique(),
+            index: 0,
+            program_id: Pubkey::new_unique(),
+            accounts: vec![],
+            data: vec![],
+            approvals: vec![false; members],
+            expires_at,
+            executed: false,
+        }
+    }
+
+    #[test]
+    fn members_and_threshold_are_validated() {
+        let (a, b) = (Pubkey::new_unique(), Pubkey::new_unique());
+
+        validate_members(&[a, b], 2).unwrap();
+        assert!(validate_members(&[a, b], 0).is_err());
+        assert!(validate_members(&[a, b], 3).is_err());
+        assert!(validate_members(&[a, a], 1).is_err());
+        assert!(validate_members(&[], 0).is_err());
+        assert!(validate_members(&[a; MAX_MEMBERS + 1], 1).is_err());
+    }
+
+    #[test]
+    fn proposal_executes_once_threshold_is_met() {
+        let mut proposal = proposal(3, 100);
+
+        proposal.approve(0, 10).unwrap();
+        assert!(proposal.approve(0, 10).is_err());
+        assert!(proposal.check_executable(2, 10).is_err());
+        proposal.approve(2, 10).unwrap();
+        proposal.check_executable(2, 10).unwrap();
+
+        proposal.executed = true;
+        assert!(proposal.check_executable(2, 10).is_err());
+        assert!(proposal.approve(1, 10).is_err());
+    }
+
+    #[test]
+    fn only_multisig_may_sign_proposal_instruction() {
+        let (multisig, vault) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let account = |pubkey, is_signer| ProposalAccount {
+            pubkey,
+            is_signer,
+            is_writable: true,
+        };
+
+        validate_signers(
+            &[
+                account(multisig, true),
+                account(vault, true),
+                account(Pubkey::new_unique(), false),
+            ],
+            &multisig,
+            &vault,
+        )
+        .unwrap();
+        assert!(
+            validate_signers(&[account(Pubkey::new_unique(), true)], &multisig, &vault).is_err()
+        );
+    }
+
+    #[test]
+    fn vault_is_derived_from_multisig() {
+        let create_key = Pubkey::new_unique();
+        let (multisig, bump) =
+            Pubkey::find_program_address(&[b"multisig", create_key.as_ref()], &crate::ID);
+        let (vault, vault_bump) =
+            Pubkey::find_program_address(&[b"vault", multisig.as_ref()], &crate::ID);
+        let account = Multisig {
+            create_key,
+            members: vec![],
+            threshold: 1,
+            proposal_count: 0,
+            bump,
+            vault_bump,
+        };
+
+        assert_eq!(account.vault_address().unwrap(), vault);
+    }
+
+    #[test]
+    fn expired_proposal_cannot_be_approved_or_executed() {
+        let mut proposal = proposal(2, 100);
+        proposal.approve(0, 99).unwrap();
+
+        assert!(proposal.approve(1, 100).is_err());
+        assert!(proposal.check_executable(1, 100).is_err());
+    }
+}
@@ -1,70 +1,816 @@
+use std::collections::BTreeSet;
+
 use anchor_lang::prelude::*;
-use anchor_spl::{
-    token::{self, Mint, Token, TokenAccount, Transfer},
+use anchor_lang::solana_program::instruction::{
+    get_stack_height, Instruction, TRANSACTION_LEVEL_STACK_HEIGHT,
+};
+use anchor_lang::system_program;
+use anchor_spl::associated_token::AssociatedToken;
+use anchor_spl::token::spl_token::native_mint;
+use anchor_spl::token_interface::{
+    self, Burn, CloseAccount, Mint, MintTo, SyncNative, TokenAccount, TokenInterface,
+    TransferChecked,
+};
+use mock_oracle::PriceFeed;
+use solana_instructions_sysvar::{
+    self as instructions_sysvar, load_current_index_checked, load_instruction_at_checked,
 };
 
 declare_id!("Fgsiva1LWG6DaAWAx6tughzWhes3tFkYiAUHS5VQfCZH");
 
+pub const ANCHOR_DISCRIMINATOR_SIZE: usize = 8;
+// 批量存取时每个 mint 在 remaining_accounts 中占用的账户数，顺序与 Deposit / Withdraw 的字段一致
+pub const BATCH_DEPOSIT_ACCOUNTS: usize = 10;
+pub const BATCH_WITHDRAW_ACCOUNTS: usize = 9;
+// 估值时每个持仓占用的账户数：user_account, vault, mint, price_feed
+pub const PORTFOLIO_ACCOUNTS: usize = 4;
+// 估值结果的计价精度
+pub const QUOTE_DECIMALS: u8 = 6;
+
 #[program]
 pub mod tokenbank {
     use super::*;
 
     pub fn initialize(ctx: Context<Initialize>) -> Result<()> {
-        ctx.accounts.bank.authority = ctx.accounts.authority.key();
+        let bank = &mut ctx.accounts.bank;
+        bank.authority = ctx.accounts.authority.key();
+        bank.pending_authority = None;
+        bank.paused = false;
+        bank.withdraw_fee_bps = 0;
+        bank.oracle_authority = ctx.accounts.authority.key();
+        Ok(())
+    }
+
+    // 设置提取手续费（基点），不得超过 MAX_WITHDRAW_FEE_BPS
+    pub fn set_withdraw_fee(ctx: Context<AdminOnly>, fee_bps: u16) -> Result<()> {
+        let bank = &mut ctx.accounts.bank;
+        let old_fee_bps = bank.withdraw_fee_bps;
+        bank.set_withdraw_fee(fee_bps)?;
+
+        emit!(WithdrawFeeUpdated {
+            old_fee_bps,
+            new_fee_bps: fee_bps,
+        });
+        Ok(())
+    }
+
+    // authority 把某个 mint 累积的手续费全部转出
+    pub fn collect_fees(ctx: Context<CollectFees>) -> Result<()> {
+        let amount = ctx.accounts.treasury.amount;
+        transfer_from_vault(
+            &ctx.accounts.token_program,
+            &ctx.accounts.mint,
+            ctx.accounts.bank.to_account_info(),
+            ctx.bumps.bank,
+            &ctx.accounts.treasury,
+            ctx.accounts.destination.to_account_info(),
+            amount,
+        )?;
+
+        emit!(FeesCollected {
+            mint: ctx.accounts.mint.key(),
+            destination: ctx.accounts.destination.key(),
+            amount,
+        });
+        Ok(())
+    }
+
+    // 暂停/恢复存取款，仅 authority 可调用
+    pub fn set_paused(ctx: Context<AdminOnly>, paused: bool) -> Result<()> {
+        ctx.accounts.bank.paused = paused;
+        Ok(())
+    }
+
+    // 只有该 authority 发布的价格账户会被 get_portfolio_value 采用
+    pub fn set_oracle_authority(ctx: Context<AdminOnly>, oracle_authority: Pubkey) -> Result<()> {
+        ctx.accounts.bank.oracle_authority = oracle_authority;
+        Ok(())
+    }
+
+    // 两步转移 authority：当前 authority 提名，新 authority 签名接受后才生效
+    pub fn propose_authority(ctx: Context<AdminOnly>, new_authority: Pubkey) -> Result<()> {
+        ctx.accounts.bank.pending_authority = Some(new_authority);
+        Ok(())
+    }
+
+    pub fn accept_authority(ctx: Context<AcceptAuthority>) -> Result<()> {
+        ctx.accounts
+            .bank
+            .accept_authority(ctx.accounts.new_authority.key())
+    }
+
+    // 把旧版 Bank（只有 authority）扩容为当前格式，新增字段取默认值，新增的租金由 payer 支付
+    pub fn migrate_bank(ctx: Context<MigrateBank>) -> Result<()> {
+        let info = ctx.accounts.bank.to_account_info();
+        require_keys_eq!(*info.owner, crate::ID, TokenBankError::InvalidLegacyBank);
+        let authority = legacy_bank_authority(&info.try_borrow_data()?)?;
+        require_keys_eq!(
+            authority,
+            ctx.accounts.authority.key(),
+            TokenBankError::Unauthorized
+        );
+
+        let space = ANCHOR_DISCRIMINATOR_SIZE + Bank::INIT_SPACE;
+        let shortfall = Rent::get()?
+            .minimum_balance(space)
+            .saturating_sub(info.lamports());
+        if shortfall > 0 {
+            system_program::transfer(
+                CpiContext::new(
+                    system_program::ID,
+                    system_program::Transfer {
+                        from: ctx.accounts.payer.to_account_info(),
+                        to: info.clone(),
+                    },
+                ),
+                shortfall,
+            )?;
+        }
+        info.resize(space)?;
+
+        let bank = Bank {
+            authority,
+            pending_authority: None,
+            paused: false,
+            withdraw_fee_bps: 0,
+            oracle_authority: authority,
+        };
+        bank.try_serialize(&mut &mut info.try_borrow_mut_data()?[..])?;
+        Ok(())
+    }
+
+    // 将 mint 加入白名单，为其创建 Vault 账户
+    pub fn register_mint(ctx: Context<RegisterMint>) -> Result<()> {
+        let vault = &mut ctx.accounts.vault;
+        vault.mint = ctx.accounts.mint.key();
+        vault.allowed = true;
         Ok(())
     }
 
+    pub fn set_mint_allowed(ctx: Context<SetMintAllowed>, allowed: bool) -> Result<()> {
+        ctx.accounts.vault.allowed = allowed;
+        Ok(())
+    }
+
+    // 设置金库限额（以资产数量计），0 表示不限制
+    pub fn set_vault_limits(
+        ctx: Context<SetVaultLimits>,
+        deposit_cap: u64,
+        max_user_balance: u64,
+        epoch_withdraw_limit: u64,
+    ) -> Result<()> {
+        let vault = &mut ctx.accounts.vault;
+        vault.deposit_cap = deposit_cap;
+        vault.max_user_balance = max_user_balance;
+        vault.epoch_withdraw_limit = epoch_withdraw_limit;
+        Ok(())
+    }
+
+    // 把直接转入金库 ATA 的收益计入 total_assets，由现有份额共享；待迁移的旧余额不计入
+    pub fn sync_assets(ctx: Context<SyncAssets>) -> Result<()> {
+        ctx.accounts
+            .vault
+            .sync_assets(ctx.accounts.tokenbank_ata.amount)?;
+        Ok(())
+    }
+
+    // deposit 会在首次存款时自动创建 UserAccount，这里保留用于兼容
     pub fn create_user_account(ctx: Context<CreateUserAccount>) -> Result<()> {
-        ctx.accounts.user_account.deposit_amount = 0;
+        let user_account = &mut ctx.accounts.user_account;
+        user_account.owner = ctx.accounts.owner.key();
+        user_account.mint = ctx.accounts.mint.key();
+        user_account.shares = 0;
         Ok(())
     }
 
-    pub fn deposit(ctx: Context<Deposit>, amount: u64) -> Result<()> {
-        let transfer_ctx = CpiContext::new(
-            Token::id(),
-            Transfer {
-                from: ctx.accounts.depositor_ata.to_account_info(),
-                to: ctx.accounts.tokenbank_ata.to_account_info(),
-                authority: ctx.accounts.depositor.to_account_info(),
-            },
+    // 旧版 UserAccount 只按 owner 派生，没有记录 mint，无法判断余额属于哪种代币
+    // 由 bank authority 指定 mint，把旧余额迁移到 (owner, mint) 账本，并关闭旧账户把租金退给 owner
+    // 把旧版本使用的、由 bank PDA 拥有的代币账户余额转入标准 ATA，记为待迁移的旧余额
+    // 不传 legacy_vault 表示旧金库就是标准 ATA，此时未计入 total_assets 的余额即为旧存款
+    pub fn sweep_legacy_vault(ctx: Context<SweepLegacyVault>) -> Result<()> {
+        let mint_key = ctx.accounts.mint.key();
+        let swept = match &ctx.accounts.legacy_vault {
+            Some(legacy_vault) => {
+                // 手续费金库和奖励金库同样由 bank 拥有，不能被当作旧金库
+                for seed in [b"treasury".as_ref(), b"reward_vault".as_ref()] {
+                    let (reserved, _) =
+                        Pubkey::find_program_address(&[seed, mint_key.as_ref()], &crate::ID);
+                    require_keys_neq!(
+                        legacy_vault.key(),
+                        reserved,
+                        TokenBankError::InvalidLegacyVault
+                    );
+                }
+
+                let tokenbank_ata = &mut ctx.accounts.tokenbank_ata;
+                let balance_before = tokenbank_ata.amount;
+                transfer_from_vault(
+                    &ctx.accounts.token_program,
+                    &ctx.accounts.mint,
+                    ctx.accounts.bank.to_account_info(),
+                    ctx.bumps.bank,
+                    legacy_vault,
+                    tokenbank_ata.to_account_info(),
+                    legacy_vault.amount,
+                )?;
+                tokenbank_ata.reload()?;
+                tokenbank_ata.amount.checked_sub(balance_before).unwrap()
+            }
+            None => ctx
+                .accounts
+                .vault
+                .unaccounted_assets(ctx.accounts.tokenbank_ata.amount),
+        };
+        ctx.accounts.vault.record_legacy_sweep(swept)
+    }
+
+    pub fn migrate_user_account(ctx: Context<MigrateUserAccount>) -> Result<()> {
+        let legacy_account = &ctx.accounts.legacy_user_account;
+        require_keys_eq!(
+            *legacy_account.owner,
+            crate::ID,
+            TokenBankError::InvalidLegacyAccount
         );
+        let deposit_amount = legacy_deposit_amount(&legacy_account.try_borrow_data()?)?;
+
+        // 旧余额对应的代币需先由 sweep_legacy_vault 转入金库 ATA，按当前汇率计入，相当于一次已到账的存款
+        let vault = &mut ctx.accounts.vault;
+        let shares = vault.migrate_legacy_balance(deposit_amount)?;
+
+        let user_account = &mut ctx.accounts.user_account;
+        user_account.owner = ctx.accounts.owner.key();
+        user_account.mint = ctx.accounts.mint.key();
+        user_account.add_shares(vault, shares, Clock::get()?.unix_timestamp)?;
 
-        token::transfer(transfer_ctx, amount)?;
-        ctx.accounts.user_account.deposit_amount += amount;
+        // 关闭旧账户：转出全部 lamports，归还给 System Program 并清空数据
+        let owner_info = ctx.accounts.owner.to_account_info();
+        let legacy_info = legacy_account.to_account_info();
+        **owner_info.lamports.borrow_mut() = owner_info
+            .lamports()
+            .checked_add(legacy_info.lamports())
+            .unwrap();
+        **legacy_info.lamports.borrow_mut() = 0;
+        legacy_info.assign(&system_program::ID);
+        legacy_info.resize(0)?;
 
         Ok(())
     }
 
+    pub fn deposit(ctx: Context<Deposit>, amount: u64) -> Result<()> {
+        ctx.accounts.deposit_shares(amount)?;
+        Ok(())
+    }
+
+    // 锁定存款：到期前（或按线性释放计划归属前）这部分份额不能提取
+    pub fn deposit_locked(
+        ctx: Context<DepositLocked>,
+        amount: u64,
+        lock_id: u64,
+        schedule: LockSchedule,
+    ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let (cliff_ts, end_ts) = schedule.resolve(now)?;
+
+        let shares = ctx.accounts.deposit.deposit_shares(amount)?;
+
+        let user_account = &mut ctx.accounts.deposit.user_account;
+        user_account.locked_shares = user_account
+            .locked_shares
+            .checked_add(shares)
+            .ok_or(TokenBankError::MathOverflow)?;
+        user_account.active_locks += 1;
+
+        ctx.accounts.lock_record.set_inner(LockRecord {
+            user_account: user_account.key(),
+            lock_id,
+            total_shares: shares,
+            released_shares: 0,
+            start_ts: now,
+            cliff_ts,
+            end_ts,
+        });
+        Ok(())
+    }
+
+    // 把已归属的锁定份额释放为可提取份额，全部释放后关闭锁定记录
+    pub fn release_vested(ctx: Context<ReleaseVested>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let lock_record = &mut ctx.accounts.lock_record;
+        let releasable = lock_record.releasable_shares(now)?;
+
+        lock_record.released_shares += releasable;
+        let user_account = &mut ctx.accounts.user_account;
+        user_account.locked_shares -= releasable;
+
+        if lock_record.released_shares == lock_record.total_shares {
+            user_account.active_locks -= 1;
+            lock_record.close(ctx.accounts.owner.to_account_info())?;
+        }
+        Ok(())
+    }
+
+    // 按资产数量提取，销毁的份额向上取整
     pub fn withdraw(ctx: Context<Withdraw>, amount: u64) -> Result<()> {
+        let shares = ctx
+            .accounts
+            .vault
+            .convert_to_shares(amount, Rounding::Up)?;
+
+        ctx.accounts.burn_and_transfer(shares, amount, ctx.bumps.bank)
+    }
+
+    // 按份额数量赎回，得到的资产向下取整
+    pub fn redeem(ctx: Context<Withdraw>, shares: u64) -> Result<()> {
+        let amount = ctx
+            .accounts
+            .vault
+            .convert_to_assets(shares, Rounding::Down)?;
+
+        ctx.accounts.burn_and_transfer(shares, amount, ctx.bumps.bank)
+    }
+
+    // 一笔指令存入多个 mint，每组账户按 Deposit 的规则校验，任一组失败则整笔回滚
+    pub fn batch_deposit<'info>(
+        ctx: Context<'info, BatchDeposit<'info>>,
+        amounts: Vec<u64>,
+    ) -> Result<()> {
+        let groups = batch_groups(ctx.remaining_accounts, amounts.len(), BATCH_DEPOSIT_ACCOUNTS)?;
+        for (mut accounts, amount) in groups.zip(amounts) {
+            let mut deposit = Deposit::try_accounts(
+                ctx.program_id,
+                &mut accounts,
+                &[],
+                &mut DepositBumps::default(),
+                &mut BTreeSet::new(),
+            )?;
+            require_keys_eq!(
+                deposit.depositor.key(),
+                ctx.accounts.depositor.key(),
+                TokenBankError::BatchAccountMismatch
+            );
+
+            deposit.deposit_shares(amount)?;
+            // 同一 mint 可能在后面的组中再次出现，先把本组的状态写回账户
+            deposit.exit(ctx.program_id)?;
+        }
+        Ok(())
+    }
+
+    // 一笔指令从多个 mint 按资产数量提取，每组账户按 Withdraw 的规则校验
+    pub fn batch_withdraw<'info>(
+        ctx: Context<'info, BatchWithdraw<'info>>,
+        amounts: Vec<u64>,
+    ) -> Result<()> {
+        let groups = batch_groups(ctx.remaining_accounts, amounts.len(), BATCH_WITHDRAW_ACCOUNTS)?;
+        for (mut accounts, amount) in groups.zip(amounts) {
+            let mut bumps = WithdrawBumps::default();
+            let mut withdraw = Withdraw::try_accounts(
+                ctx.program_id,
+                &mut accounts,
+                &[],
+                &mut bumps,
+                &mut BTreeSet::new(),
+            )?;
+            require_keys_eq!(
+                withdraw.receiver.key(),
+                ctx.accounts.receiver.key(),
+                TokenBankError::BatchAccountMismatch
+            );
+
+            let shares = withdraw.vault.convert_to_shares(amount, Rounding::Up)?;
+            withdraw.burn_and_transfer(shares, amount, bumps.bank)?;
+            withdraw.exit(ctx.program_id)?;
+        }
+        Ok(())
+    }
+
+    // 直接存入 SOL：包装到临时 wSOL 账户，转入金库后关闭临时账户
+    pub fn deposit_sol(ctx: Context<DepositSol>, amount: u64) -> Result<()> {
+        // 只有刚创建的账户 owner 为默认值，已有账户不会被重新初始化
+        let user_account = &mut ctx.accounts.user_account;
+        if user_account.owner == Pubkey::default() {
+            user_account.owner = ctx.accounts.depositor.key();
+            user_account.mint = ctx.accounts.mint.key();
+        }
+
+        let wrap_ctx = CpiContext::new(
+            ctx.accounts.system_program.key(),
+            system_program::Transfer {
+                from: ctx.accounts.depositor.to_account_info(),
+                to: ctx.accounts.wsol_account.to_account_info(),
+            },
+        );
+        system_program::transfer(wrap_ctx, amount)?;
+        token_interface::sync_native(CpiContext::new(
+            ctx.accounts.token_program.key(),
+            SyncNative {
+                account: ctx.accounts.wsol_account.to_account_info(),
+            },
+        ))?;
+
+        let received = transfer_into_vault(
+            &ctx.accounts.token_program,
+            &ctx.accounts.mint,
+            ctx.accounts.wsol_account.to_account_info(),
+            ctx.accounts.depositor.to_account_info(),
+            &mut ctx.accounts.tokenbank_ata,
+            amount,
+        )?;
+        credit_deposit(
+            &mut ctx.accounts.vault,
+            &mut ctx.accounts.user_account,
+            received,
+        )?;
+
+        // 临时账户的租金退还给存款人
+        close_wsol_account(
+            &ctx.accounts.token_program,
+            ctx.accounts.wsol_account.to_account_info(),
+            ctx.accounts.depositor.to_account_info(),
+        )
+    }
+
+    // 按 lamports 数量提取 SOL：金库转入临时 wSOL 账户后关闭，代币解包为 lamports
+    pub fn withdraw_sol(ctx: Context<WithdrawSol>, amount: u64) -> Result<()> {
+        let shares = ctx
+            .accounts
+            .vault
+            .convert_to_shares(amount, Rounding::Up)?;
+        require!(shares > 0, TokenBankError::ZeroShares);
+        ctx.accounts.user_account.remove_shares(
+            &mut ctx.accounts.vault,
+            shares,
+            Clock::get()?.unix_timestamp,
+        )?;
+
+        let fee = pay_withdrawal(
+            &ctx.accounts.token_program,
+            &ctx.accounts.mint,
+            ctx.accounts.bank.to_account_info(),
+            ctx.bumps.bank,
+            &ctx.accounts.tokenbank_ata,
+            ctx.accounts.wsol_account.to_account_info(),
+            ctx.accounts.treasury.to_account_info(),
+            amount,
+            ctx.accounts.bank.withdraw_fee_bps,
+        )?;
+        ctx.accounts
+            .vault
+            .record_withdrawal(amount, Clock::get()?.epoch)?;
+        ctx.accounts.vault.burn_shares(shares, amount)?;
+
+        emit!(WithdrawEvent {
+            owner: ctx.accounts.receiver.key(),
+            mint: ctx.accounts.mint.key(),
+            shares,
+            assets: amount,
+            fee,
+        });
+
+        close_wsol_account(
+            &ctx.accounts.token_program,
+            ctx.accounts.wsol_account.to_account_info(),
+            ctx.accounts.receiver.to_account_info(),
+        )
+    }
+
+    // 授权 spender 代为提取最多 amount 个代币，重复调用会覆盖之前的额度
+    pub fn approve(ctx: Context<Approve>, amount: u64, expires_at: i64) -> Result<()> {
         require!(
-            ctx.accounts.user_account.deposit_amount >= amount,
-            TokenBankError::InsufficientFunds
+            expires_at > Clock::get()?.unix_timestamp,
+            TokenBankError::AllowanceExpired
         );
+        ctx.accounts.allowance.set_inner(Allowance {
+            owner: ctx.accounts.owner.key(),
+            mint: ctx.accounts.mint.key(),
+            spender: ctx.accounts.spender.key(),
+            amount,
+            expires_at,
+        });
+        Ok(())
+    }
+
+    pub fn revoke(_ctx: Context<Revoke>) -> Result<()> {
+        Ok(())
+    }
+
+    // 被授权的 spender 从 owner 的账本提取代币到任意代币账户
+    pub fn withdraw_from(ctx: Context<WithdrawFrom>, amount: u64) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        ctx.accounts.allowance.spend(amount, now)?;
+
+        let shares = ctx
+            .accounts
+            .vault
+            .convert_to_shares(amount, Rounding::Up)?;
+        require!(shares > 0, TokenBankError::ZeroShares);
+        ctx.accounts
+            .user_account
+            .remove_shares(&mut ctx.accounts.vault, shares, now)?;
+
+        let fee = pay_withdrawal(
+            &ctx.accounts.token_program,
+            &ctx.accounts.mint,
+            ctx.accounts.bank.to_account_info(),
+            ctx.bumps.bank,
+            &ctx.accounts.tokenbank_ata,
+            ctx.accounts.receiver_ata.to_account_info(),
+            ctx.accounts.treasury.to_account_info(),
+            amount,
+            ctx.accounts.bank.withdraw_fee_bps,
+        )?;
+        ctx.accounts
+            .vault
+            .record_withdrawal(amount, Clock::get()?.epoch)?;
+        ctx.accounts.vault.burn_shares(shares, amount)?;
+
+        emit!(WithdrawEvent {
+            owner: ctx.accounts.owner.key(),
+            mint: ctx.accounts.mint.key(),
+            shares,
+            assets: amount,
+            fee,
+        });
+        Ok(())
+    }
+
+    // 管理员为 mint 创建流动性凭证代币，mint authority 为 bank PDA
+    pub fn create_receipt_mint(_ctx: Context<CreateReceiptMint>) -> Result<()> {
+        Ok(())
+    }
+
+    // 存款后不记入 UserAccount，而是按份额铸造可转让的凭证代币
+    pub fn deposit_liquid(ctx: Context<DepositLiquid>, amount: u64) -> Result<()> {
+        let received = transfer_into_vault(
+            &ctx.accounts.token_program,
+            &ctx.accounts.mint,
+            ctx.accounts.depositor_ata.to_account_info(),
+            ctx.accounts.depositor.to_account_info(),
+            &mut ctx.accounts.tokenbank_ata,
+            amount,
+        )?;
+
+        let vault = &mut ctx.accounts.vault;
+        let shares = vault.convert_to_shares(received, Rounding::Down)?;
+        require!(shares > 0, TokenBankError::ZeroShares);
+        vault.mint_shares(shares, received)?;
+        vault.check_deposit_cap()?;
 
-        // 使用 seeds 作为签名，确保只有银行账户可以提取资金
         let bank_seeds = &[b"bank".as_ref(), &[ctx.bumps.bank]];
         let signer = &[&bank_seeds[..]];
 
-        let transfer_ctx = CpiContext::new_with_signer(
-            Token::id(),
-            Transfer {
-                from: ctx.accounts.tokenbank_ata.to_account_info(),
-                to: ctx.accounts.receiver_ata.to_account_info(),
-                authority: ctx.accounts.bank.to_account_info(),
-            },
-            signer,  // 使用 seeds 作为 PDA 签名
+        token_interface::mint_to(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.key(),
+                MintTo {
+                    mint: ctx.accounts.receipt_mint.to_account_info(),
+                    to: ctx.accounts.depositor_receipt_ata.to_account_info(),
+                    authority: ctx.accounts.bank.to_account_info(),
+                },
+                signer,
+            ),
+            shares,
+        )
+    }
+
+    // 任何凭证持有者都可以销毁凭证，按份额赎回代币到任意代币账户
+    pub fn redeem_receipt(ctx: Context<RedeemReceipt>, shares: u64) -> Result<()> {
+        require!(shares > 0, TokenBankError::ZeroShares);
+
+        let amount = ctx
+            .accounts
+            .vault
+            .convert_to_assets(shares, Rounding::Down)?;
+
+        token_interface::burn(
+            CpiContext::new(
+                ctx.accounts.token_program.key(),
+                Burn {
+                    mint: ctx.accounts.receipt_mint.to_account_info(),
+                    from: ctx.accounts.holder_receipt_ata.to_account_info(),
+                    authority: ctx.accounts.holder.to_account_info(),
+                },
+            ),
+            shares,
+        )?;
+
+        // 凭证赎回同样收取提取手续费
+        let fee = pay_withdrawal(
+            &ctx.accounts.token_program,
+            &ctx.accounts.mint,
+            ctx.accounts.bank.to_account_info(),
+            ctx.bumps.bank,
+            &ctx.accounts.tokenbank_ata,
+            ctx.accounts.receiver_ata.to_account_info(),
+            ctx.accounts.treasury.to_account_info(),
+            amount,
+            ctx.accounts.bank.withdraw_fee_bps,
+        )?;
+        ctx.accounts
+            .vault
+            .record_withdrawal(amount, Clock::get()?.epoch)?;
+        ctx.accounts.vault.burn_shares(shares, amount)?;
+
+        emit!(WithdrawEvent {
+            owner: ctx.accounts.holder.key(),
+            mint: ctx.accounts.mint.key(),
+            shares,
+            assets: amount,
+            fee,
+        });
+        Ok(())
+    }
+
+    // 为 vault 配置奖励代币，创建由 bank PDA 持有的奖励金库
+    pub fn initialize_rewards(ctx: Context<InitializeRewards>, reward_rate: u64) -> Result<()> {
+        let vault = &mut ctx.accounts.vault;
+        vault.accrue_rewards(Clock::get()?.unix_timestamp)?;
+        vault.reward_mint = ctx.accounts.reward_mint.key();
+        vault.reward_rate = reward_rate;
+        Ok(())
+    }
+
+    // authority 向奖励金库注入奖励，只有已注入的奖励会被发放
+    pub fn fund_rewards(ctx: Context<FundRewards>, amount: u64) -> Result<()> {
+        ctx.accounts
+            .vault
+            .accrue_rewards(Clock::get()?.unix_timestamp)?;
+
+        let received = transfer_into_vault(
+            &ctx.accounts.reward_token_program,
+            &ctx.accounts.reward_mint,
+            ctx.accounts.funder_ata.to_account_info(),
+            ctx.accounts.authority.to_account_info(),
+            &mut ctx.accounts.reward_vault,
+            amount,
+        )?;
+
+        let vault = &mut ctx.accounts.vault;
+        vault.reward_remaining = vault
+            .reward_remaining
+            .checked_add(received)
+            .ok_or(TokenBankError::MathOverflow)?;
+        Ok(())
+    }
+
+    // 修改每秒发放的奖励数量，之前的时间段按旧速率结算
+    pub fn set_reward_rate(ctx: Context<SetRewardRate>, reward_rate: u64) -> Result<()> {
+        let vault = &mut ctx.accounts.vault;
+        vault.accrue_rewards(Clock::get()?.unix_timestamp)?;
+        vault.reward_rate = reward_rate;
+        Ok(())
+    }
+
+    pub fn claim_rewards(ctx: Context<ClaimRewards>) -> Result<()> {
+        let vault = &mut ctx.accounts.vault;
+        vault.accrue_rewards(Clock::get()?.unix_timestamp)?;
+
+        let user_account = &mut ctx.accounts.user_account;
+        user_account.settle_rewards(vault.acc_reward_per_share)?;
+        let amount = user_account.rewards_owed;
+        if amount == 0 {
+            return Ok(());
+        }
+        user_account.rewards_owed = 0;
+
+        transfer_from_vault(
+            &ctx.accounts.reward_token_program,
+            &ctx.accounts.reward_mint,
+            ctx.accounts.bank.to_account_info(),
+            ctx.bumps.bank,
+            &ctx.accounts.reward_vault,
+            ctx.accounts.receiver_reward_ata.to_account_info(),
+            amount,
+        )
+    }
+
+    // 闪电贷：同一笔交易中必须在之后调用 flash_repay 归还本金和手续费
+    pub fn flash_borrow(ctx: Context<FlashBorrow>, amount: u64) -> Result<()> {
+        // 只允许在交易顶层调用，否则 instructions sysvar 的当前指令不是本指令
+        require_eq!(
+            get_stack_height(),
+            TRANSACTION_LEVEL_STACK_HEIGHT,
+            TokenBankError::FlashLoanCpi
         );
 
-        token::transfer(transfer_ctx, amount)?;
+        let instructions = ctx.accounts.instructions.to_account_info();
+        let current_index = load_current_index_checked(&instructions)? as usize;
+        let vault_key = ctx.accounts.vault.key();
+
+        let mut repaid = false;
+        let mut index = current_index + 1;
+        while let Ok(ix) = load_instruction_at_checked(index, &instructions) {
+            if is_flash_repay(&ix, &vault_key, amount) {
+                repaid = true;
+                break;
+            }
+            index += 1;
+        }
+        require!(repaid, TokenBankError::MissingFlashRepay);
+
+        ctx.accounts.vault.flash_loan_amount = amount;
+
+        transfer_from_vault(
+            &ctx.accounts.token_program,
+            &ctx.accounts.mint,
+            ctx.accounts.bank.to_account_info(),
+            ctx.bumps.bank,
+            &ctx.accounts.tokenbank_ata,
+            ctx.accounts.borrower_ata.to_account_info(),
+            amount,
+        )
+    }
+
+    // 归还闪电贷，手续费留在金库中，由全部份额共享
+    pub fn flash_repay(ctx: Context<FlashRepay>, amount: u64) -> Result<()> {
+        require!(
+            ctx.accounts.vault.flash_loan_amount == amount,
+            TokenBankError::InvalidFlashRepay
+        );
 
-        ctx.accounts.user_account.deposit_amount -= amount;
+        let due = amount
+            .checked_add(flash_loan_fee(amount)?)
+            .ok_or(TokenBankError::MathOverflow)?;
+        let received = transfer_into_vault(
+            &ctx.accounts.token_program,
+            &ctx.accounts.mint,
+            ctx.accounts.repayer_ata.to_account_info(),
+            ctx.accounts.repayer.to_account_info(),
+            &mut ctx.accounts.tokenbank_ata,
+            due,
+        )?;
+        require!(received >= due, TokenBankError::InvalidFlashRepay);
 
+        // 手续费作为收益计入总资产，由全部份额共享
+        let vault = &mut ctx.accounts.vault;
+        vault.total_assets = vault
+            .total_assets
+            .checked_add(received - amount)
+            .ok_or(TokenBankError::MathOverflow)?;
+        vault.flash_loan_amount = 0;
         Ok(())
     }
 
+    // 以下 preview 指令不修改状态，通过 return data 返回预计数量
+    pub fn preview_deposit(ctx: Context<Preview>, amount: u64) -> Result<u64> {
+        ctx.accounts.vault.convert_to_shares(amount, Rounding::Down)
+    }
+
+    // withdraw(amount) 需要销毁的份额，amount 为扣除手续费前的提取数量
+    pub fn preview_withdraw(ctx: Context<Preview>, amount: u64) -> Result<u64> {
+        ctx.accounts.vault.convert_to_shares(amount, Rounding::Up)
+    }
+
+    // 赎回 shares 后扣除提取手续费实际收到的资产
+    pub fn preview_redeem(ctx: Context<Preview>, shares: u64) -> Result<u64> {
+        let assets = ctx
+            .accounts
+            .vault
+            .convert_to_assets(shares, Rounding::Down)?;
+        let fee = withdraw_fee(assets, ctx.accounts.bank.withdraw_fee_bps)?;
+        Ok(assets - fee)
+    }
+
+    // 按预言机价格估算 owner 在各个 mint 的持仓总价值，以 QUOTE_DECIMALS 精度返回
+    pub fn get_portfolio_value<'info>(
+        ctx: Context<'info, GetPortfolioValue<'info>>,
+    ) -> Result<u64> {
+        let positions = ctx.remaining_accounts;
+        require!(
+            !positions.is_empty() && positions.len().is_multiple_of(PORTFOLIO_ACCOUNTS),
+            TokenBankError::BatchLengthMismatch
+        );
+
+        // 同一持仓重复传入会被重复计价
+        let keys: Vec<Pubkey> = positions
+            .chunks_exact(PORTFOLIO_ACCOUNTS)
+            .flat_map(|position| [position[0].key(), position[2].key()])
+            .collect();
+        require_unique_keys(&keys)?;
+
+        let now = Clock::get()?.unix_timestamp;
+        let mut total: u64 = 0;
+        for position in positions.chunks_exact(PORTFOLIO_ACCOUNTS) {
+            let value = position_value(
+                &ctx.accounts.owner.key(),
+                &ctx.accounts.bank.oracle_authority,
+                position,
+                now,
+            )?;
+            total = total
+                .checked_add(value)
+                .ok_or(TokenBankError::MathOverflow)?;
+        }
+        Ok(total)
+    }
+
     pub fn close_user_account(ctx: Context<CloseUserAccount>) -> Result<()> {
         require!(
-            ctx.accounts.user_account.deposit_amount == 0,
+            ctx.accounts.user_account.active_locks == 0,
+            TokenBankError::LocksOutstanding
+        );
+        require!(
+            ctx.accounts.user_account.shares == 0 && ctx.accounts.user_account.rewards_owed == 0,
             TokenBankError::AccountNotEmpty
         );
 
@@ -77,7 +823,7 @@ pub struct Initialize<'info> {
     #[account(
         init,
         payer = authority,
-        space = 8 + 32,
+        space = ANCHOR_DISCRIMINATOR_SIZE + Bank::INIT_SPACE,
         seeds = [b"bank"],
         bump
     )]
@@ -88,111 +834,1820 @@ pub struct Initialize<'info> {
 }
 
 #[derive(Accounts)]
-pub struct CreateUserAccount<'info> {
+pub struct AdminOnly<'info> {
     #[account(
-        init,
-        payer = owner,
-        space = 8 + 8, // discriminator + deposit_amount
-        seeds = [b"user", owner.key().as_ref()],
+        mut,
+        seeds = [b"bank"],
+        bump,
+        has_one = authority @ TokenBankError::Unauthorized
+    )]
+    pub bank: Account<'info, Bank>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptAuthority<'info> {
+    #[account(
+        mut,
+        seeds = [b"bank"],
         bump
     )]
-    pub user_account: Account<'info, UserAccount>,
+    pub bank: Account<'info, Bank>,
+    pub new_authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateBank<'info> {
+    /// CHECK: 旧版 Bank（discriminator + authority），在指令中校验 owner 和数据格式
+    #[account(
+        mut,
+        seeds = [b"bank"],
+        bump
+    )]
+    pub bank: UncheckedAccount<'info>,
+    pub authority: Signer<'info>,
+    // 租金由 payer 支付，authority 可以是多签 PDA
     #[account(mut)]
-    pub owner: Signer<'info>,
+    pub payer: Signer<'info>,
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct Deposit<'info> {
-    #[account(mut,
-        seeds = [b"bank"],  // 添加这个约束
+pub struct RegisterMint<'info> {
+    #[account(
+        seeds = [b"bank"],
         bump,
+        has_one = authority @ TokenBankError::Unauthorized
     )]
     pub bank: Account<'info, Bank>,
 
+    // 每个 mint 一个 Vault 账户，存在且 allowed 表示该 mint 在白名单中
     #[account(
-        mut,
-        seeds = [b"user", depositor.key().as_ref()],
+        init,
+        payer = payer,
+        space = ANCHOR_DISCRIMINATOR_SIZE + Vault::INIT_SPACE,
+        seeds = [b"vault", mint.key().as_ref()],
         bump
     )]
-    pub user_account: Account<'info, UserAccount>,
-    
-    pub mint: Account<'info, Mint>,
-    
+    pub vault: Account<'info, Vault>,
+    #[account(mint::token_program = token_program)]
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    // 金库使用 bank PDA 的标准 ATA；init_if_needed 避免他人抢先创建该 ATA 导致注册失败
     #[account(
-        mut,
-        token::mint = mint,
-        token::authority = depositor
+        init_if_needed,
+        payer = payer,
+        associated_token::mint = mint,
+        associated_token::authority = bank,
+        associated_token::token_program = token_program
     )]
-    pub depositor_ata: Account<'info, TokenAccount>,
+    pub tokenbank_ata: InterfaceAccount<'info, TokenAccount>,
 
-    // authority is bank, 这样程序可以通过 PDA seeds 签名来授权代币转移
-    
+    // 提取手续费先归集到该账户，由 collect_fees 转出
     #[account(
-        mut,
+        init,
+        payer = payer,
+        seeds = [b"treasury", mint.key().as_ref()],
+        bump,
         token::mint = mint,
-        token::authority = bank
+        token::authority = bank,
+        token::token_program = token_program
     )]
-    pub tokenbank_ata: Account<'info, TokenAccount>,
-    pub depositor: Signer<'info>,
-    pub token_program: Program<'info, Token>,
+    pub treasury: InterfaceAccount<'info, TokenAccount>,
+    pub authority: Signer<'info>,
+    // 租金由 payer 支付，authority 可以是多签 PDA
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct Withdraw<'info> {
+pub struct SetMintAllowed<'info> {
     #[account(
-        mut,
         seeds = [b"bank"],
-        bump
+        bump,
+        has_one = authority @ TokenBankError::Unauthorized
     )]
     pub bank: Account<'info, Bank>,
-
     #[account(
         mut,
-        seeds = [b"user", receiver.key().as_ref()],
+        seeds = [b"vault", vault.mint.as_ref()],
         bump
     )]
-    pub user_account: Account<'info, UserAccount>,
-    pub mint: Account<'info, Mint>,
-    
+    pub vault: Account<'info, Vault>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetVaultLimits<'info> {
+    #[account(
+        seeds = [b"bank"],
+        bump,
+        has_one = authority @ TokenBankError::Unauthorized
+    )]
+    pub bank: Account<'info, Bank>,
     #[account(
         mut,
-        token::mint = mint,
-        token::authority = bank
+        seeds = [b"vault", vault.mint.as_ref()],
+        bump
     )]
-    pub tokenbank_ata: Account<'info, TokenAccount>,
+    pub vault: Account<'info, Vault>,
+    pub authority: Signer<'info>,
+}
 
+#[derive(Accounts)]
+pub struct SyncAssets<'info> {
+    #[account(
+        seeds = [b"bank"],
+        bump,
+        has_one = authority @ TokenBankError::Unauthorized
+    )]
+    pub bank: Account<'info, Bank>,
+    // 闪电贷进行中时 ATA 余额低于 total_assets，不能同步
     #[account(
         mut,
-        token::mint = mint,
-        token::authority = receiver
+        seeds = [b"vault", mint.key().as_ref()],
+        bump,
+        has_one = mint,
+        constraint = vault.flash_loan_amount == 0 @ TokenBankError::FlashLoanActive
     )]
-    pub receiver_ata: Account<'info, TokenAccount>,
-    pub receiver: Signer<'info>,
-    pub token_program: Program<'info, Token>,
+    pub vault: Account<'info, Vault>,
+    #[account(mint::token_program = token_program)]
+    pub mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        associated_token::mint = mint,
+        associated_token::authority = bank,
+        associated_token::token_program = token_program
+    )]
+    pub tokenbank_ata: InterfaceAccount<'info, TokenAccount>,
+    pub authority: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
 }
 
 #[derive(Accounts)]
-pub struct CloseUserAccount<'info> {
+pub struct CreateUserAccount<'info> {
+    // 每个 (owner, mint) 一个账本，避免存入 A 代币却取出 B 代币
     #[account(
-        mut,
-        close = owner,
-        seeds = [b"user", owner.key().as_ref()],
+        init,
+        payer = owner,
+        space = ANCHOR_DISCRIMINATOR_SIZE + UserAccount::INIT_SPACE,
+        seeds = [b"user", owner.key().as_ref(), mint.key().as_ref()],
         bump
     )]
     pub user_account: Account<'info, UserAccount>,
+    pub mint: InterfaceAccount<'info, Mint>,
     #[account(mut)]
     pub owner: Signer<'info>,
+    pub system_program: Program<'info, System>,
 }
 
-#[account]
-pub struct Bank {
-    pub authority: Pubkey,
-}
+#[derive(Accounts)]
+pub struct SweepLegacyVault<'info> {
+    #[account(
+        seeds = [b"bank"],
+        bump,
+        has_one = authority @ TokenBankError::Unauthorized
+    )]
+    pub bank: Account<'info, Bank>,
+    #[account(
+        mut,
+        seeds = [b"vault", mint.key().as_ref()],
+        bump,
+        has_one = mint,
+        constraint = vault.flash_loan_amount == 0 @ TokenBankError::FlashLoanActive
+    )]
+    pub vault: Account<'info, Vault>,
+    #[account(mint::token_program = token_program)]
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    // 旧版本的金库可以是 bank 拥有的任意代币账户
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = bank,
+        token::token_program = token_program
+    )]
+    pub legacy_vault: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = bank,
+        associated_token::token_program = token_program
+    )]
+    pub tokenbank_ata: InterfaceAccount<'info, TokenAccount>,
+    pub authority: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateUserAccount<'info> {
+    #[account(
+        seeds = [b"bank"],
+        bump,
+        has_one = authority @ TokenBankError::Unauthorized
+    )]
+    pub bank: Account<'info, Bank>,
+
+    /// CHECK: 旧版 UserAccount（discriminator + deposit_amount），在指令中校验 owner 和数据格式
+    #[account(
+        mut,
+        seeds = [b"user", owner.key().as_ref()],
+        bump
+    )]
+    pub legacy_user_account: UncheckedAccount<'info>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = ANCHOR_DISCRIMINATOR_SIZE + UserAccount::INIT_SPACE,
+        seeds = [b"user", owner.key().as_ref(), mint.key().as_ref()],
+        bump
+    )]
+    pub user_account: Account<'info, UserAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", mint.key().as_ref()],
+        bump,
+        has_one = mint
+    )]
+    pub vault: Account<'info, Vault>,
+    #[account(mint::token_program = token_program)]
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    // 旧账户的租金退还给 owner
+    #[account(mut)]
+    pub owner: SystemAccount<'info>,
+    pub authority: Signer<'info>,
+    // 租金由 payer 支付，authority 可以是多签 PDA
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Deposit<'info> {
+    #[account(mut,
+        seeds = [b"bank"],  // 添加这个约束
+        bump,
+        constraint = !bank.paused @ TokenBankError::BankPaused
+    )]
+    pub bank: Account<'info, Bank>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", mint.key().as_ref()],
+        bump,
+        has_one = mint,
+        constraint = vault.allowed @ TokenBankError::MintNotAllowed,
+        // 闪电贷进行中金库余额不代表真实资产，暂停存取
+        constraint = vault.flash_loan_amount == 0 @ TokenBankError::FlashLoanActive
+    )]
+    pub vault: Account<'info, Vault>,
+
+    // 首次存款时创建，owner 和 mint 在 deposit_shares 中写入
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = ANCHOR_DISCRIMINATOR_SIZE + UserAccount::INIT_SPACE,
+        seeds = [b"user", depositor.key().as_ref(), mint.key().as_ref()],
+        bump
+    )]
+    pub user_account: Account<'info, UserAccount>,
+    
+    #[account(mint::token_program = token_program)]
+    pub mint: InterfaceAccount<'info, Mint>,
+    
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = depositor,
+        token::token_program = token_program
+    )]
+    pub depositor_ata: InterfaceAccount<'info, TokenAccount>,
+
+    // authority is bank, 这样程序可以通过 PDA seeds 签名来授权代币转移
+    
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = bank,
+        associated_token::token_program = token_program
+    )]
+    pub tokenbank_ata: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut)]
+    pub depositor: Signer<'info>,
+    // 支付 UserAccount 租金，可以与 depositor 相同
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    // Token Program 或 Token-2022 Program
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> Deposit<'info> {
+    // 转入代币并按实际到账数量铸造份额，返回铸造的份额
+    fn deposit_shares(&mut self, amount: u64) -> Result<u64> {
+        // 只有刚创建的账户 owner 为默认值，已有账户不会被重新初始化
+        if self.user_account.owner == Pubkey::default() {
+            self.user_account.owner = self.depositor.key();
+            self.user_account.mint = self.mint.key();
+        }
+
+        let received = transfer_into_vault(
+            &self.token_program,
+            &self.mint,
+            self.depositor_ata.to_account_info(),
+            self.depositor.to_account_info(),
+            &mut self.tokenbank_ata,
+            amount,
+        )?;
+
+        credit_deposit(&mut self.vault, &mut self.user_account, received)
+    }
+}
+
+// 其余账户通过 remaining_accounts 传入，每组对应一个 Deposit
+#[derive(Accounts)]
+pub struct BatchDeposit<'info> {
+    pub depositor: Signer<'info>,
+}
+
+// 其余账户通过 remaining_accounts 传入，每组对应一个 Withdraw
+#[derive(Accounts)]
+pub struct BatchWithdraw<'info> {
+    pub receiver: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(amount: u64, lock_id: u64)]
+pub struct DepositLocked<'info> {
+    pub deposit: Deposit<'info>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = ANCHOR_DISCRIMINATOR_SIZE + LockRecord::INIT_SPACE,
+        seeds = [b"lock", deposit.user_account.key().as_ref(), &lock_id.to_le_bytes()],
+        bump
+    )]
+    pub lock_record: Account<'info, LockRecord>,
+    // 与 UserAccount 的租金由同一个 payer 支付
+    #[account(mut, address = deposit.payer.key())]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ReleaseVested<'info> {
+    #[account(
+        mut,
+        seeds = [b"user", owner.key().as_ref(), mint.key().as_ref()],
+        bump,
+        has_one = mint
+    )]
+    pub user_account: Account<'info, UserAccount>,
+    pub mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        seeds = [b"lock", user_account.key().as_ref(), &lock_record.lock_id.to_le_bytes()],
+        bump,
+        has_one = user_account
+    )]
+    pub lock_record: Account<'info, LockRecord>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct Withdraw<'info> {
+    #[account(
+        mut,
+        seeds = [b"bank"],
+        bump,
+        constraint = !bank.paused @ TokenBankError::BankPaused
+    )]
+    pub bank: Account<'info, Bank>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", mint.key().as_ref()],
+        bump,
+        has_one = mint,
+        constraint = vault.allowed @ TokenBankError::MintNotAllowed,
+        // 闪电贷进行中金库余额不代表真实资产，暂停存取
+        constraint = vault.flash_loan_amount == 0 @ TokenBankError::FlashLoanActive
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        mut,
+        seeds = [b"user", receiver.key().as_ref(), mint.key().as_ref()],
+        bump,
+        has_one = mint
+    )]
+    pub user_account: Account<'info, UserAccount>,
+    #[account(mint::token_program = token_program)]
+    pub mint: InterfaceAccount<'info, Mint>,
+    
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = bank,
+        associated_token::token_program = token_program
+    )]
+    pub tokenbank_ata: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = receiver,
+        token::token_program = token_program
+    )]
+    pub receiver_ata: InterfaceAccount<'info, TokenAccount>,
+    // 手续费金库，bank PDA 持有，与存款金库分开以免手续费计入总资产
+    #[account(
+        mut,
+        seeds = [b"treasury", mint.key().as_ref()],
+        bump
+    )]
+    pub treasury: InterfaceAccount<'info, TokenAccount>,
+    pub receiver: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+impl<'info> Withdraw<'info> {
+    // 扣减用户份额并从金库转出代币
+    fn burn_and_transfer(&mut self, shares: u64, amount: u64, bank_bump: u8) -> Result<()> {
+        require!(shares > 0, TokenBankError::ZeroShares);
+        self.user_account
+            .remove_shares(&mut self.vault, shares, Clock::get()?.unix_timestamp)?;
+
+        let fee = pay_withdrawal(
+            &self.token_program,
+            &self.mint,
+            self.bank.to_account_info(),
+            bank_bump,
+            &self.tokenbank_ata,
+            self.receiver_ata.to_account_info(),
+            self.treasury.to_account_info(),
+            amount,
+            self.bank.withdraw_fee_bps,
+        )?;
+        self.vault.record_withdrawal(amount, Clock::get()?.epoch)?;
+        self.vault.burn_shares(shares, amount)?;
+
+        emit!(WithdrawEvent {
+            owner: self.receiver.key(),
+            mint: self.mint.key(),
+            shares,
+            assets: amount,
+            fee,
+        });
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct DepositSol<'info> {
+    #[account(
+        seeds = [b"bank"],
+        bump,
+        constraint = !bank.paused @ TokenBankError::BankPaused
+    )]
+    pub bank: Account<'info, Bank>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", mint.key().as_ref()],
+        bump,
+        has_one = mint,
+        constraint = vault.allowed @ TokenBankError::MintNotAllowed,
+        constraint = vault.flash_loan_amount == 0 @ TokenBankError::FlashLoanActive
+    )]
+    pub vault: Account<'info, Vault>,
+
+    // 首次存款时创建，owner 和 mint 在 deposit_sol 中写入
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = ANCHOR_DISCRIMINATOR_SIZE + UserAccount::INIT_SPACE,
+        seeds = [b"user", depositor.key().as_ref(), mint.key().as_ref()],
+        bump
+    )]
+    pub user_account: Account<'info, UserAccount>,
+    #[account(address = native_mint::ID, mint::token_program = token_program)]
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    // 指令内创建并关闭的临时 wSOL 账户
+    #[account(
+        init,
+        payer = depositor,
+        seeds = [b"wsol", depositor.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = depositor,
+        token::token_program = token_program
+    )]
+    pub wsol_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = bank,
+        associated_token::token_program = token_program
+    )]
+    pub tokenbank_ata: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut)]
+    pub depositor: Signer<'info>,
+    // 支付 UserAccount 的租金，可以与 depositor 不同
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawSol<'info> {
+    #[account(
+        seeds = [b"bank"],
+        bump,
+        constraint = !bank.paused @ TokenBankError::BankPaused
+    )]
+    pub bank: Account<'info, Bank>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", mint.key().as_ref()],
+        bump,
+        has_one = mint,
+        constraint = vault.allowed @ TokenBankError::MintNotAllowed,
+        constraint = vault.flash_loan_amount == 0 @ TokenBankError::FlashLoanActive
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        mut,
+        seeds = [b"user", receiver.key().as_ref(), mint.key().as_ref()],
+        bump,
+        has_one = mint
+    )]
+    pub user_account: Account<'info, UserAccount>,
+    #[account(address = native_mint::ID, mint::token_program = token_program)]
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        init,
+        payer = receiver,
+        seeds = [b"wsol", receiver.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = receiver,
+        token::token_program = token_program
+    )]
+    pub wsol_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = bank,
+        associated_token::token_program = token_program
+    )]
+    pub tokenbank_ata: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [b"treasury", mint.key().as_ref()],
+        bump
+    )]
+    pub treasury: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut)]
+    pub receiver: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Approve<'info> {
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = ANCHOR_DISCRIMINATOR_SIZE + Allowance::INIT_SPACE,
+        seeds = [
+            b"allowance",
+            owner.key().as_ref(),
+            mint.key().as_ref(),
+            spender.key().as_ref()
+        ],
+        bump
+    )]
+    pub allowance: Account<'info, Allowance>,
+    pub mint: InterfaceAccount<'info, Mint>,
+    /// CHECK: 只记录 spender 的地址，可以是钱包或其他程序的 PDA
+    pub spender: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Revoke<'info> {
+    #[account(
+        mut,
+        close = owner,
+        seeds = [
+            b"allowance",
+            owner.key().as_ref(),
+            allowance.mint.as_ref(),
+            allowance.spender.as_ref()
+        ],
+        bump
+    )]
+    pub allowance: Account<'info, Allowance>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawFrom<'info> {
+    #[account(
+        seeds = [b"bank"],
+        bump,
+        constraint = !bank.paused @ TokenBankError::BankPaused
+    )]
+    pub bank: Account<'info, Bank>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", mint.key().as_ref()],
+        bump,
+        has_one = mint,
+        constraint = vault.allowed @ TokenBankError::MintNotAllowed,
+        constraint = vault.flash_loan_amount == 0 @ TokenBankError::FlashLoanActive
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        mut,
+        seeds = [b"user", owner.key().as_ref(), mint.key().as_ref()],
+        bump,
+        has_one = mint
+    )]
+    pub user_account: Account<'info, UserAccount>,
+    #[account(mint::token_program = token_program)]
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"allowance",
+            owner.key().as_ref(),
+            mint.key().as_ref(),
+            spender.key().as_ref()
+        ],
+        bump
+    )]
+    pub allowance: Account<'info, Allowance>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = bank,
+        associated_token::token_program = token_program
+    )]
+    pub tokenbank_ata: InterfaceAccount<'info, TokenAccount>,
+
+    // spender 可以把代币转入任意账户
+    #[account(
+        mut,
+        token::mint = mint,
+        token::token_program = token_program
+    )]
+    pub receiver_ata: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [b"treasury", mint.key().as_ref()],
+        bump
+    )]
+    pub treasury: InterfaceAccount<'info, TokenAccount>,
+    pub owner: SystemAccount<'info>,
+    pub spender: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct CollectFees<'info> {
+    #[account(
+        seeds = [b"bank"],
+        bump,
+        has_one = authority @ TokenBankError::Unauthorized
+    )]
+    pub bank: Account<'info, Bank>,
+    #[account(mint::token_program = token_program)]
+    pub mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        seeds = [b"treasury", mint.key().as_ref()],
+        bump
+    )]
+    pub treasury: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = mint,
+        token::token_program = token_program
+    )]
+    pub destination: InterfaceAccount<'info, TokenAccount>,
+    pub authority: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct CreateReceiptMint<'info> {
+    #[account(
+        seeds = [b"bank"],
+        bump,
+        has_one = authority @ TokenBankError::Unauthorized
+    )]
+    pub bank: Account<'info, Bank>,
+    #[account(
+        seeds = [b"vault", mint.key().as_ref()],
+        bump,
+        has_one = mint
+    )]
+    pub vault: Account<'info, Vault>,
+    #[account(mint::token_program = token_program)]
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    // 凭证与份额 1:1，精度与底层代币一致
+    #[account(
+        init,
+        payer = payer,
+        seeds = [b"receipt", mint.key().as_ref()],
+        bump,
+        mint::decimals = mint.decimals,
+        mint::authority = bank,
+        mint::token_program = token_program
+    )]
+    pub receipt_mint: InterfaceAccount<'info, Mint>,
+    pub authority: Signer<'info>,
+    // 租金由 payer 支付，authority 可以是多签 PDA
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct DepositLiquid<'info> {
+    #[account(
+        seeds = [b"bank"],
+        bump,
+        constraint = !bank.paused @ TokenBankError::BankPaused
+    )]
+    pub bank: Account<'info, Bank>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", mint.key().as_ref()],
+        bump,
+        has_one = mint,
+        constraint = vault.allowed @ TokenBankError::MintNotAllowed,
+        // 闪电贷进行中金库余额不代表真实资产，暂停存取
+        constraint = vault.flash_loan_amount == 0 @ TokenBankError::FlashLoanActive
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(mint::token_program = token_program)]
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [b"receipt", mint.key().as_ref()],
+        bump,
+        mint::token_program = token_program
+    )]
+    pub receipt_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = depositor,
+        token::token_program = token_program
+    )]
+    pub depositor_ata: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        token::mint = receipt_mint,
+        token::authority = depositor,
+        token::token_program = token_program
+    )]
+    pub depositor_receipt_ata: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = bank,
+        associated_token::token_program = token_program
+    )]
+    pub tokenbank_ata: InterfaceAccount<'info, TokenAccount>,
+    pub depositor: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct RedeemReceipt<'info> {
+    #[account(
+        seeds = [b"bank"],
+        bump,
+        constraint = !bank.paused @ TokenBankError::BankPaused
+    )]
+    pub bank: Account<'info, Bank>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", mint.key().as_ref()],
+        bump,
+        has_one = mint,
+        constraint = vault.allowed @ TokenBankError::MintNotAllowed,
+        // 闪电贷进行中金库余额不代表真实资产，暂停存取
+        constraint = vault.flash_loan_amount == 0 @ TokenBankError::FlashLoanActive
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(mint::token_program = token_program)]
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [b"receipt", mint.key().as_ref()],
+        bump,
+        mint::token_program = token_program
+    )]
+    pub receipt_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        token::mint = receipt_mint,
+        token::authority = holder,
+        token::token_program = token_program
+    )]
+    pub holder_receipt_ata: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = bank,
+        associated_token::token_program = token_program
+    )]
+    pub tokenbank_ata: InterfaceAccount<'info, TokenAccount>,
+
+    // 赎回的代币可以转入任意账户
+    #[account(
+        mut,
+        token::mint = mint,
+        token::token_program = token_program
+    )]
+    pub receiver_ata: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"treasury", mint.key().as_ref()],
+        bump
+    )]
+    pub treasury: InterfaceAccount<'info, TokenAccount>,
+    pub holder: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeRewards<'info> {
+    #[account(
+        seeds = [b"bank"],
+        bump,
+        has_one = authority @ TokenBankError::Unauthorized
+    )]
+    pub bank: Account<'info, Bank>,
+    #[account(
+        mut,
+        seeds = [b"vault", vault.mint.as_ref()],
+        bump
+    )]
+    pub vault: Account<'info, Vault>,
+    #[account(mint::token_program = reward_token_program)]
+    pub reward_mint: InterfaceAccount<'info, Mint>,
+
+    // 每个 vault 一个奖励金库，只能初始化一次
+    #[account(
+        init,
+        payer = payer,
+        seeds = [b"reward_vault", vault.mint.as_ref()],
+        bump,
+        token::mint = reward_mint,
+        token::authority = bank,
+        token::token_program = reward_token_program
+    )]
+    pub reward_vault: InterfaceAccount<'info, TokenAccount>,
+    pub authority: Signer<'info>,
+    // 租金由 payer 支付，authority 可以是多签 PDA
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub reward_token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct FundRewards<'info> {
+    #[account(
+        seeds = [b"bank"],
+        bump,
+        has_one = authority @ TokenBankError::Unauthorized
+    )]
+    pub bank: Account<'info, Bank>,
+    #[account(
+        mut,
+        seeds = [b"vault", vault.mint.as_ref()],
+        bump,
+        has_one = reward_mint
+    )]
+    pub vault: Account<'info, Vault>,
+    #[account(mint::token_program = reward_token_program)]
+    pub reward_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        seeds = [b"reward_vault", vault.mint.as_ref()],
+        bump
+    )]
+    pub reward_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = reward_mint,
+        token::authority = authority,
+        token::token_program = reward_token_program
+    )]
+    pub funder_ata: InterfaceAccount<'info, TokenAccount>,
+    pub authority: Signer<'info>,
+    pub reward_token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct SetRewardRate<'info> {
+    #[account(
+        seeds = [b"bank"],
+        bump,
+        has_one = authority @ TokenBankError::Unauthorized
+    )]
+    pub bank: Account<'info, Bank>,
+    #[account(
+        mut,
+        seeds = [b"vault", vault.mint.as_ref()],
+        bump
+    )]
+    pub vault: Account<'info, Vault>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimRewards<'info> {
+    #[account(
+        seeds = [b"bank"],
+        bump
+    )]
+    pub bank: Account<'info, Bank>,
+    #[account(
+        mut,
+        seeds = [b"vault", mint.key().as_ref()],
+        bump,
+        has_one = mint,
+        has_one = reward_mint
+    )]
+    pub vault: Account<'info, Vault>,
+    pub mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        seeds = [b"user", owner.key().as_ref(), mint.key().as_ref()],
+        bump,
+        has_one = mint
+    )]
+    pub user_account: Account<'info, UserAccount>,
+    #[account(mint::token_program = reward_token_program)]
+    pub reward_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        seeds = [b"reward_vault", mint.key().as_ref()],
+        bump
+    )]
+    pub reward_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = reward_mint,
+        token::token_program = reward_token_program
+    )]
+    pub receiver_reward_ata: InterfaceAccount<'info, TokenAccount>,
+    pub owner: Signer<'info>,
+    pub reward_token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct FlashBorrow<'info> {
+    #[account(
+        seeds = [b"bank"],
+        bump,
+        constraint = !bank.paused @ TokenBankError::BankPaused
+    )]
+    pub bank: Account<'info, Bank>,
+
+    // flash_loan_amount 不为 0 表示已有未归还的闪电贷，禁止嵌套借款
+    #[account(
+        mut,
+        seeds = [b"vault", mint.key().as_ref()],
+        bump,
+        has_one = mint,
+        constraint = vault.allowed @ TokenBankError::MintNotAllowed,
+        constraint = vault.flash_loan_amount == 0 @ TokenBankError::FlashLoanActive
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(mint::token_program = token_program)]
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = bank,
+        associated_token::token_program = token_program
+    )]
+    pub tokenbank_ata: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        token::mint = mint,
+        token::token_program = token_program
+    )]
+    pub borrower_ata: InterfaceAccount<'info, TokenAccount>,
+    pub borrower: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+
+    /// CHECK: instructions sysvar，用于检查之后是否有匹配的 flash_repay
+    #[account(address = instructions_sysvar::ID)]
+    pub instructions: UncheckedAccount<'info>,
+}
+
+// vault 必须是第一个账户，flash_borrow 据此匹配归还指令
+#[derive(Accounts)]
+pub struct FlashRepay<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", mint.key().as_ref()],
+        bump,
+        has_one = mint
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(mint::token_program = token_program)]
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        seeds = [b"bank"],
+        bump
+    )]
+    pub bank: Account<'info, Bank>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = bank,
+        associated_token::token_program = token_program
+    )]
+    pub tokenbank_ata: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = repayer,
+        token::token_program = token_program
+    )]
+    pub repayer_ata: InterfaceAccount<'info, TokenAccount>,
+    pub repayer: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+// 闪电贷手续费 0.09%
+pub const FLASH_LOAN_FEE_BPS: u64 = 9;
+
+// 手续费向上取整，借款再小也至少支付 1 个最小单位
+pub fn flash_loan_fee(amount: u64) -> Result<u64> {
+    mul_div(amount, FLASH_LOAN_FEE_BPS as u128, 10_000, Rounding::Up)
+}
+
+// 判断指令是否为归还同一 vault、同等金额的 flash_repay
+fn is_flash_repay(ix: &Instruction, vault: &Pubkey, amount: u64) -> bool {
+    ix.program_id == crate::ID
+        && ix.data.len() == 16
+        && ix.data.starts_with(instruction::FlashRepay::DISCRIMINATOR)
+        && ix.data[8..] == amount.to_le_bytes()
+        && ix.accounts.first().map(|meta| &meta.pubkey) == Some(vault)
+}
+
+#[derive(Accounts)]
+pub struct Preview<'info> {
+    #[account(
+        seeds = [b"bank"],
+        bump
+    )]
+    pub bank: Account<'info, Bank>,
+    #[account(
+        seeds = [b"vault", mint.key().as_ref()],
+        bump,
+        has_one = mint
+    )]
+    pub vault: Account<'info, Vault>,
+    #[account(mint::token_program = token_program)]
+    pub mint: InterfaceAccount<'info, Mint>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+// 持仓账户通过 remaining_accounts 传入，每 PORTFOLIO_ACCOUNTS 个一组
+#[derive(Accounts)]
+pub struct GetPortfolioValue<'info> {
+    #[account(
+        seeds = [b"bank"],
+        bump
+    )]
+    pub bank: Account<'info, Bank>,
+    /// CHECK: 只读视图，只用于校验各组 UserAccount 的 owner
+    pub owner: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CloseUserAccount<'info> {
+    #[account(
+        mut,
+        close = owner,
+        seeds = [b"user", owner.key().as_ref(), mint.key().as_ref()],
+        bump,
+        has_one = mint
+    )]
+    pub user_account: Account<'info, UserAccount>,
+    pub mint: InterfaceAccount<'info, Mint>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+}
+
+// 从 from 转入 bank PDA 持有的代币账户，返回实际收到的数量
+fn transfer_into_vault<'info>(
+    token_program: &Interface<'info, TokenInterface>,
+    mint: &InterfaceAccount<'info, Mint>,
+    from: AccountInfo<'info>,
+    authority: AccountInfo<'info>,
+    vault_ata: &mut InterfaceAccount<'info, TokenAccount>,
+    amount: u64,
+) -> Result<u64> {
+    let balance_before = vault_ata.amount;
+
+    let transfer_ctx = CpiContext::new(
+        token_program.key(),
+        TransferChecked {
+            from,
+            mint: mint.to_account_info(),
+            to: vault_ata.to_account_info(),
+            authority,
+        },
+    );
+
+    token_interface::transfer_checked(transfer_ctx, amount, mint.decimals)?;
+
+    // Token-2022 的 transfer-fee 扩展会在转账时扣除手续费，按实际收到的数量记账
+    vault_ata.reload()?;
+    Ok(vault_ata.amount.checked_sub(balance_before).unwrap())
+}
+
+// 把 remaining_accounts 按每组 group_len 个账户切分，组数必须与金额数一致
+fn batch_groups<'info>(
+    accounts: &'info [AccountInfo<'info>],
+    batch_len: usize,
+    group_len: usize,
+) -> Result<std::slice::ChunksExact<'info, AccountInfo<'info>>> {
+    require!(
+        batch_len > 0 && accounts.len() == batch_len * group_len,
+        TokenBankError::BatchLengthMismatch
+    );
+    Ok(accounts.chunks_exact(group_len))
+}
+
+// 按存款前的汇率铸造份额（向下取整）并记入用户账本，返回铸造的份额
+fn credit_deposit(
+    vault: &mut Vault,
+    user_account: &mut UserAccount,
+    received: u64,
+) -> Result<u64> {
+    let shares = vault.convert_to_shares(received, Rounding::Down)?;
+    require!(shares > 0, TokenBankError::ZeroShares);
+    vault.mint_shares(shares, received)?;
+    vault.check_deposit_cap()?;
+
+    // 份额变化前先结算待领取奖励
+    user_account.add_shares(vault, shares, Clock::get()?.unix_timestamp)?;
+    vault.check_user_balance(user_account.shares)?;
+    Ok(shares)
+}
+
+// 关闭 wSOL 账户，剩余的 lamports（含包装的 SOL 和租金）全部退给 owner
+fn close_wsol_account<'info>(
+    token_program: &Interface<'info, TokenInterface>,
+    wsol_account: AccountInfo<'info>,
+    owner: AccountInfo<'info>,
+) -> Result<()> {
+    token_interface::close_account(CpiContext::new(
+        token_program.key(),
+        CloseAccount {
+            account: wsol_account,
+            destination: owner.clone(),
+            authority: owner,
+        },
+    ))
+}
+
+// 从金库转出 amount：扣除手续费后转给接收方，手续费转入 treasury，返回手续费
+#[allow(clippy::too_many_arguments)]
+fn pay_withdrawal<'info>(
+    token_program: &Interface<'info, TokenInterface>,
+    mint: &InterfaceAccount<'info, Mint>,
+    bank: AccountInfo<'info>,
+    bank_bump: u8,
+    vault_ata: &InterfaceAccount<'info, TokenAccount>,
+    to: AccountInfo<'info>,
+    treasury: AccountInfo<'info>,
+    amount: u64,
+    fee_bps: u16,
+) -> Result<u64> {
+    let fee = withdraw_fee(amount, fee_bps)?;
+    transfer_from_vault(
+        token_program,
+        mint,
+        bank.clone(),
+        bank_bump,
+        vault_ata,
+        to,
+        amount - fee,
+    )?;
+    if fee > 0 {
+        transfer_from_vault(token_program, mint, bank, bank_bump, vault_ata, treasury, fee)?;
+    }
+    Ok(fee)
+}
+
+// 校验一组持仓账户并按预言机价格计算其价值
+fn position_value<'info>(
+    owner: &Pubkey,
+    oracle_authority: &Pubkey,
+    position: &'info [AccountInfo<'info>],
+    now: i64,
+) -> Result<u64> {
+    let user_account = Account::<UserAccount>::try_from(&position[0])?;
+    let vault = Account::<Vault>::try_from(&position[1])?;
+    let mint = InterfaceAccount::<Mint>::try_from(&position[2])?;
+    let price_feed = Account::<PriceFeed>::try_from(&position[3])?;
+
+    // 账户都由程序创建，校验它们指向同一个 owner 和 mint
+    let mint_key = mint.key();
+    require!(
+        user_account.owner == *owner
+            && user_account.mint == mint_key
+            && vault.mint == mint_key
+            && price_feed.mint == mint_key,
+        TokenBankError::InvalidPortfolioAccount
+    );
+    require_keys_eq!(
+        price_feed.authority,
+        *oracle_authority,
+        TokenBankError::UntrustedPriceFeed
+    );
+    require!(!price_feed.is_stale(now), TokenBankError::StalePrice);
+
+    let assets = vault.convert_to_assets(user_account.shares, Rounding::Down)?;
+    quote_value(assets, mint.decimals, price_feed.price, price_feed.expo)
+}
+
+pub fn require_unique_keys(keys: &[Pubkey]) -> Result<()> {
+    for (i, key) in keys.iter().enumerate() {
+        require!(
+            !keys[..i].contains(key),
+            TokenBankError::DuplicatePortfolioAccount
+        );
+    }
+    Ok(())
+}
+
+// assets（mint 精度）* price * 10^expo，换算为 QUOTE_DECIMALS 精度，向下取整
+pub fn quote_value(assets: u64, decimals: u8, price: i64, expo: i32) -> Result<u64> {
+    require!(price > 0, TokenBankError::InvalidPrice);
+
+    let value = (assets as u128)
+        .checked_mul(price as u128)
+        .ok_or(TokenBankError::MathOverflow)?;
+    let exponent = QUOTE_DECIMALS as i32 + expo - decimals as i32;
+    let scale = 10u128
+        .checked_pow(exponent.unsigned_abs())
+        .ok_or(TokenBankError::MathOverflow)?;
+    let value = if exponent >= 0 {
+        value.checked_mul(scale).ok_or(TokenBankError::MathOverflow)?
+    } else {
+        value / scale
+    };
+    u64::try_from(value).map_err(|_| error!(TokenBankError::MathOverflow))
+}
+
+// 从 bank PDA 持有的代币账户转出代币
+fn transfer_from_vault<'info>(
+    token_program: &Interface<'info, TokenInterface>,
+    mint: &InterfaceAccount<'info, Mint>,
+    bank: AccountInfo<'info>,
+    bank_bump: u8,
+    vault_ata: &InterfaceAccount<'info, TokenAccount>,
+    to: AccountInfo<'info>,
+    amount: u64,
+) -> Result<()> {
+    // 使用 seeds 作为签名，确保只有银行账户可以提取资金
+    let bank_seeds = &[b"bank".as_ref(), &[bank_bump]];
+    let signer = &[&bank_seeds[..]];
+
+    let transfer_ctx = CpiContext::new_with_signer(
+        token_program.key(),
+        TransferChecked {
+            from: vault_ata.to_account_info(),
+            mint: mint.to_account_info(),
+            to,
+            authority: bank,
+        },
+        signer,  // 使用 seeds 作为 PDA 签名
+    );
+
+    token_interface::transfer_checked(transfer_ctx, amount, mint.decimals)
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Bank {
+    pub authority: Pubkey,
+    // 已提名、等待接受的新 authority
+    pub pending_authority: Option<Pubkey>,
+    pub paused: bool,
+    // 提取手续费，单位为基点
+    pub withdraw_fee_bps: u16,
+    // 可信的价格发布者，任何人都能在 mock_oracle 中创建价格账户
+    pub oracle_authority: Pubkey,
+}
+
+// 手续费上限 5%，防止管理员通过手续费没收存款
+pub const MAX_WITHDRAW_FEE_BPS: u16 = 500;
+
+pub fn withdraw_fee(amount: u64, fee_bps: u16) -> Result<u64> {
+    mul_div(amount, fee_bps as u128, 10_000, Rounding::Down)
+}
+
+impl Bank {
+    pub fn set_withdraw_fee(&mut self, fee_bps: u16) -> Result<()> {
+        require!(
+            fee_bps <= MAX_WITHDRAW_FEE_BPS,
+            TokenBankError::FeeTooHigh
+        );
+        self.withdraw_fee_bps = fee_bps;
+        Ok(())
+    }
+
+    pub fn accept_authority(&mut self, new_authority: Pubkey) -> Result<()> {
+        require!(
+            self.pending_authority == Some(new_authority),
+            TokenBankError::InvalidPendingAuthority
+        );
+
+        self.authority = new_authority;
+        self.pending_authority = None;
+        Ok(())
+    }
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct Vault {
+    pub mint: Pubkey,
+    pub allowed: bool,
+    // 所有用户份额之和
+    pub total_shares: u64,
+    // 已计入份额的资产：存款、已迁移的旧余额、闪电贷手续费，以及 sync_assets 同步的收益
+    // 尚未迁移的旧余额不计入
+    pub total_assets: u64,
+    // 已转入金库 ATA、尚未迁移到新账本的旧余额
+    pub legacy_assets: u64,
+    // UserAccount 中记账的份额之和，只有这部分份额参与奖励分配（流动性凭证不参与）
+    pub staked_shares: u64,
+    // 奖励代币，未配置时为默认公钥
+    pub reward_mint: Pubkey,
+    // 每秒发放的奖励数量
+    pub reward_rate: u64,
+    // 每份额累计奖励，放大 REWARD_PRECISION 倍
+    pub acc_reward_per_share: u128,
+    pub last_reward_ts: i64,
+    // 已注入但尚未分配的奖励
+    pub reward_remaining: u64,
+    // 进行中的闪电贷本金，0 表示没有
+    pub flash_loan_amount: u64,
+    // 以下限额以资产数量计，0 表示不限制
+    pub deposit_cap: u64,
+    pub max_user_balance: u64,
+    pub epoch_withdraw_limit: u64,
+    // 当前统计的 epoch 及其已提取的资产
+    pub withdraw_epoch: u64,
+    pub epoch_withdrawn: u64,
+}
+
+pub const REWARD_PRECISION: u128 = 1_000_000_000_000;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Rounding {
+    Down,
+    Up,
+}
+
+// 份额和资产都加上 1 的虚拟偏移，空金库时汇率为 1:1，并抵御首笔存款的捐赠通胀攻击
+const VIRTUAL_OFFSET: u128 = 1;
+
+impl Vault {
+    pub fn convert_to_shares(&self, assets: u64, rounding: Rounding) -> Result<u64> {
+        mul_div(
+            assets,
+            self.total_shares as u128 + VIRTUAL_OFFSET,
+            self.total_assets as u128 + VIRTUAL_OFFSET,
+            rounding,
+        )
+    }
+
+    pub fn convert_to_assets(&self, shares: u64, rounding: Rounding) -> Result<u64> {
+        mul_div(
+            shares,
+            self.total_assets as u128 + VIRTUAL_OFFSET,
+            self.total_shares as u128 + VIRTUAL_OFFSET,
+            rounding,
+        )
+    }
+
+    // 金库 ATA 中既未计入份额、也不属于待迁移旧余额的代币
+    pub fn unaccounted_assets(&self, ata_amount: u64) -> u64 {
+        ata_amount
+            .saturating_sub(self.total_assets)
+            .saturating_sub(self.legacy_assets)
+    }
+
+    // 把 ATA 中未记账的代币计入 total_assets，返回新增的资产
+    pub fn sync_assets(&mut self, ata_amount: u64) -> Result<u64> {
+        let surplus = self.unaccounted_assets(ata_amount);
+        self.total_assets = self
+            .total_assets
+            .checked_add(surplus)
+            .ok_or(TokenBankError::MathOverflow)?;
+        Ok(surplus)
+    }
+
+    pub fn record_legacy_sweep(&mut self, assets: u64) -> Result<()> {
+        self.legacy_assets = self
+            .legacy_assets
+            .checked_add(assets)
+            .ok_or(TokenBankError::MathOverflow)?;
+        Ok(())
+    }
+
+    // 旧账本余额按当前汇率换成份额，空金库时为 1:1；只能迁移已转入金库 ATA 的旧余额
+    pub fn migrate_legacy_balance(&mut self, assets: u64) -> Result<u64> {
+        let legacy_assets = self
+            .legacy_assets
+            .checked_sub(assets)
+            .ok_or(TokenBankError::LegacyAssetsNotSwept)?;
+        let shares = self.convert_to_shares(assets, Rounding::Down)?;
+        require!(shares > 0, TokenBankError::ZeroShares);
+        self.mint_shares(shares, assets)?;
+        self.legacy_assets = legacy_assets;
+        Ok(shares)
+    }
+
+    pub fn mint_shares(&mut self, shares: u64, assets: u64) -> Result<()> {
+        self.total_shares = self
+            .total_shares
+            .checked_add(shares)
+            .ok_or(TokenBankError::MathOverflow)?;
+        self.total_assets = self
+            .total_assets
+            .checked_add(assets)
+            .ok_or(TokenBankError::MathOverflow)?;
+        Ok(())
+    }
+
+    // 把上次结算以来的奖励计入累计器，发放总量不超过已注入的奖励
+    pub fn accrue_rewards(&mut self, now: i64) -> Result<()> {
+        if now <= self.last_reward_ts {
+            return Ok(());
+        }
+        let elapsed = (now - self.last_reward_ts) as u64;
+        self.last_reward_ts = now;
+
+        if self.staked_shares == 0 || self.reward_rate == 0 {
+            return Ok(());
+        }
+
+        let reward = elapsed
+            .saturating_mul(self.reward_rate)
+            .min(self.reward_remaining);
+        self.acc_reward_per_share = self
+            .acc_reward_per_share
+            .checked_add(reward as u128 * REWARD_PRECISION / self.staked_shares as u128)
+            .ok_or(TokenBankError::MathOverflow)?;
+        self.reward_remaining -= reward;
+        Ok(())
+    }
+
+    pub fn check_deposit_cap(&self) -> Result<()> {
+        require!(
+            self.deposit_cap == 0 || self.total_assets <= self.deposit_cap,
+            TokenBankError::DepositCapExceeded
+        );
+        Ok(())
+    }
+
+    // 按当前汇率检查用户持有的资产是否超过上限
+    pub fn check_user_balance(&self, user_shares: u64) -> Result<()> {
+        if self.max_user_balance == 0 {
+            return Ok(());
+        }
+        let assets = self.convert_to_assets(user_shares, Rounding::Down)?;
+        require!(
+            assets <= self.max_user_balance,
+            TokenBankError::UserBalanceCapExceeded
+        );
+        Ok(())
+    }
+
+    pub fn record_withdrawal(&mut self, assets: u64, epoch: u64) -> Result<()> {
+        // 进入新的 epoch 时重新计数
+        if epoch != self.withdraw_epoch {
+            self.withdraw_epoch = epoch;
+            self.epoch_withdrawn = 0;
+        }
+        let epoch_withdrawn = self
+            .epoch_withdrawn
+            .checked_add(assets)
+            .ok_or(TokenBankError::MathOverflow)?;
+        require!(
+            self.epoch_withdraw_limit == 0 || epoch_withdrawn <= self.epoch_withdraw_limit,
+            TokenBankError::EpochWithdrawLimitExceeded
+        );
+        self.epoch_withdrawn = epoch_withdrawn;
+        Ok(())
+    }
+
+    pub fn burn_shares(&mut self, shares: u64, assets: u64) -> Result<()> {
+        self.total_shares = self
+            .total_shares
+            .checked_sub(shares)
+            .ok_or(TokenBankError::MathOverflow)?;
+        self.total_assets = self
+            .total_assets
+            .checked_sub(assets)
+            .ok_or(TokenBankError::MathOverflow)?;
+        Ok(())
+    }
+}
+
+fn mul_div(value: u64, numerator: u128, denominator: u128, rounding: Rounding) -> Result<u64> {
+    let product = (value as u128)
+        .checked_mul(numerator)
+        .ok_or(TokenBankError::MathOverflow)?;
+    let result = match rounding {
+        Rounding::Down => product / denominator,
+        Rounding::Up => product.div_ceil(denominator),
+    };
+    u64::try_from(result).map_err(|_| error!(TokenBankError::MathOverflow))
+}
 
 #[account]
+#[derive(InitSpace)]
 pub struct UserAccount {
-    pub deposit_amount: u64,
+    pub owner: Pubkey,
+    pub mint: Pubkey,
+    // 持有的金库份额，可兑换的代币数量随金库资产变化
+    pub shares: u64,
+    // shares * acc_reward_per_share，用于计算上次结算后的新增奖励
+    pub reward_debt: u128,
+    // 已结算、待领取的奖励
+    pub rewards_owed: u64,
+    // 尚未归属的锁定份额，包含在 shares 中但不能提取
+    pub locked_shares: u64,
+    // 未关闭的锁定记录数量
+    pub active_locks: u32,
+}
+
+impl UserAccount {
+    // 按当前累计器结算待领取奖励，不足一个最小单位的部分舍去
+    pub fn settle_rewards(&mut self, acc_reward_per_share: u128) -> Result<()> {
+        let accrued = reward_debt(self.shares, acc_reward_per_share)?;
+        let pending = accrued
+            .checked_sub(self.reward_debt)
+            .ok_or(TokenBankError::MathOverflow)?
+            / REWARD_PRECISION;
+        self.rewards_owed = self
+            .rewards_owed
+            .checked_add(u64::try_from(pending).map_err(|_| error!(TokenBankError::MathOverflow))?)
+            .ok_or(TokenBankError::MathOverflow)?;
+        self.reward_debt = accrued;
+        Ok(())
+    }
+
+    pub fn add_shares(&mut self, vault: &mut Vault, shares: u64, now: i64) -> Result<()> {
+        vault.accrue_rewards(now)?;
+        self.settle_rewards(vault.acc_reward_per_share)?;
+
+        self.shares = self
+            .shares
+            .checked_add(shares)
+            .ok_or(TokenBankError::MathOverflow)?;
+        vault.staked_shares = vault
+            .staked_shares
+            .checked_add(shares)
+            .ok_or(TokenBankError::MathOverflow)?;
+        self.reward_debt = reward_debt(self.shares, vault.acc_reward_per_share)?;
+        Ok(())
+    }
+
+    pub fn remove_shares(&mut self, vault: &mut Vault, shares: u64, now: i64) -> Result<()> {
+        vault.accrue_rewards(now)?;
+        self.settle_rewards(vault.acc_reward_per_share)?;
+
+        require!(shares <= self.shares, TokenBankError::InsufficientFunds);
+        require!(
+            shares <= self.shares - self.locked_shares,
+            TokenBankError::FundsLocked
+        );
+        self.shares -= shares;
+        vault.staked_shares = vault
+            .staked_shares
+            .checked_sub(shares)
+            .ok_or(TokenBankError::MathOverflow)?;
+        self.reward_debt = reward_debt(self.shares, vault.acc_reward_per_share)?;
+        Ok(())
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LockSchedule {
+    // 到指定时间戳一次性解锁
+    Until { unlock_ts: i64 },
+    // 从存款时刻开始线性释放，cliff 之前不释放任何份额
+    Vesting {
+        cliff_seconds: i64,
+        duration_seconds: i64,
+    },
+}
+
+impl LockSchedule {
+    // 转换为 (cliff_ts, end_ts)
+    pub fn resolve(&self, now: i64) -> Result<(i64, i64)> {
+        match *self {
+            LockSchedule::Until { unlock_ts } => {
+                require!(unlock_ts > now, TokenBankError::InvalidLockSchedule);
+                Ok((unlock_ts, unlock_ts))
+            }
+            LockSchedule::Vesting {
+                cliff_seconds,
+                duration_seconds,
+            } => {
+                require!(
+                    duration_seconds > 0 && (0..=duration_seconds).contains(&cliff_seconds),
+                    TokenBankError::InvalidLockSchedule
+                );
+                let end_ts = now
+                    .checked_add(duration_seconds)
+                    .ok_or(TokenBankError::MathOverflow)?;
+                Ok((now + cliff_seconds, end_ts))
+            }
+        }
+    }
+}
+
+// 每笔锁定存款一个记录，按 (UserAccount, lock_id) 派生
+#[account]
+#[derive(InitSpace)]
+pub struct LockRecord {
+    pub user_account: Pubkey,
+    pub lock_id: u64,
+    pub total_shares: u64,
+    pub released_shares: u64,
+    pub start_ts: i64,
+    pub cliff_ts: i64,
+    pub end_ts: i64,
+}
+
+impl LockRecord {
+    pub fn vested_shares(&self, now: i64) -> Result<u64> {
+        if now < self.cliff_ts {
+            return Ok(0);
+        }
+        if now >= self.end_ts {
+            return Ok(self.total_shares);
+        }
+        mul_div(
+            self.total_shares,
+            (now - self.start_ts) as u128,
+            (self.end_ts - self.start_ts) as u128,
+            Rounding::Down,
+        )
+    }
+
+    pub fn releasable_shares(&self, now: i64) -> Result<u64> {
+        Ok(self.vested_shares(now)? - self.released_shares)
+    }
+}
+
+// owner 授权 spender 代为提取的额度，按 (owner, mint, spender) 派生
+#[account]
+#[derive(InitSpace)]
+pub struct Allowance {
+    pub owner: Pubkey,
+    pub mint: Pubkey,
+    pub spender: Pubkey,
+    // 剩余可提取的代币数量
+    pub amount: u64,
+    pub expires_at: i64,
+}
+
+impl Allowance {
+    pub fn spend(&mut self, amount: u64, now: i64) -> Result<()> {
+        require!(now < self.expires_at, TokenBankError::AllowanceExpired);
+        self.amount = self
+            .amount
+            .checked_sub(amount)
+            .ok_or(TokenBankError::InsufficientAllowance)?;
+        Ok(())
+    }
+}
+
+fn reward_debt(shares: u64, acc_reward_per_share: u128) -> Result<u128> {
+    (shares as u128)
+        .checked_mul(acc_reward_per_share)
+        .ok_or(error!(TokenBankError::MathOverflow))
+}
+
+// 旧版 Bank 的数据长度：discriminator + authority
+pub const LEGACY_BANK_SIZE: usize = ANCHOR_DISCRIMINATOR_SIZE + 32;
+
+// 从旧版 Bank 数据中读取 authority
+pub fn legacy_bank_authority(data: &[u8]) -> Result<Pubkey> {
+    require!(
+        data.len() == LEGACY_BANK_SIZE && data.starts_with(Bank::DISCRIMINATOR),
+        TokenBankError::InvalidLegacyBank
+    );
+
+    let mut authority = [0u8; 32];
+    authority.copy_from_slice(&data[ANCHOR_DISCRIMINATOR_SIZE..]);
+    Ok(Pubkey::new_from_array(authority))
+}
+
+// 旧版 UserAccount 的数据长度：discriminator + deposit_amount
+pub const LEGACY_USER_ACCOUNT_SIZE: usize = ANCHOR_DISCRIMINATOR_SIZE + 8;
+
+// 从旧版 UserAccount 数据中读取 deposit_amount
+pub fn legacy_deposit_amount(data: &[u8]) -> Result<u64> {
+    require!(
+        data.len() == LEGACY_USER_ACCOUNT_SIZE && data.starts_with(UserAccount::DISCRIMINATOR),
+        TokenBankError::InvalidLegacyAccount
+    );
+
+    let mut amount = [0u8; 8];
+    amount.copy_from_slice(&data[ANCHOR_DISCRIMINATOR_SIZE..]);
+    Ok(u64::from_le_bytes(amount))
+}
+
+#[event]
+pub struct WithdrawEvent {
+    pub owner: Pubkey,
+    pub mint: Pubkey,
+    pub shares: u64,
+    // 从金库扣除的总资产，包含手续费
+    pub assets: u64,
+    pub fee: u64,
+}
+
+#[event]
+pub struct WithdrawFeeUpdated {
+    pub old_fee_bps: u16,
+    pub new_fee_bps: u16,
+}
+
+#[event]
+pub struct FeesCollected {
+    pub mint: Pubkey,
+    pub destination: Pubkey,
+    pub amount: u64,
 }
 
 #[error_code]
@@ -201,37 +2656,539 @@ pub enum TokenBankError {
     InsufficientFunds,
     #[msg("Account not empty")]
     AccountNotEmpty,
+    #[msg("Invalid legacy user account")]
+    InvalidLegacyAccount,
+    #[msg("Unauthorized")]
+    Unauthorized,
+    #[msg("Bank is paused")]
+    BankPaused,
+    #[msg("Mint is not allowed")]
+    MintNotAllowed,
+    #[msg("Signer is not the pending authority")]
+    InvalidPendingAuthority,
+    #[msg("Amount converts to zero shares")]
+    ZeroShares,
+    #[msg("Math overflow")]
+    MathOverflow,
+    #[msg("A flash loan is already active")]
+    FlashLoanActive,
+    #[msg("Flash loan must be called at the top level of the transaction")]
+    FlashLoanCpi,
+    #[msg("Missing matching flash_repay instruction")]
+    MissingFlashRepay,
+    #[msg("Invalid flash loan repayment")]
+    InvalidFlashRepay,
+    #[msg("Invalid lock schedule")]
+    InvalidLockSchedule,
+    #[msg("Funds are locked")]
+    FundsLocked,
+    #[msg("User account has outstanding locks")]
+    LocksOutstanding,
+    #[msg("Withdraw fee exceeds the maximum")]
+    FeeTooHigh,
+    #[msg("Allowance has expired")]
+    AllowanceExpired,
+    #[msg("Insufficient allowance")]
+    InsufficientAllowance,
+    #[msg("Batch accounts do not match the amounts")]
+    BatchLengthMismatch,
+    #[msg("Batch account does not belong to the signer")]
+    BatchAccountMismatch,
+    #[msg("Invalid portfolio account")]
+    InvalidPortfolioAccount,
+    #[msg("Price is stale")]
+    StalePrice,
+    #[msg("Invalid price")]
+    InvalidPrice,
+    #[msg("Vault deposit cap exceeded")]
+    DepositCapExceeded,
+    #[msg("User balance cap exceeded")]
+    UserBalanceCapExceeded,
+    #[msg("Epoch withdraw limit exceeded")]
+    EpochWithdrawLimitExceeded,
+    #[msg("Duplicate portfolio account")]
+    DuplicatePortfolioAccount,
+    #[msg("Price feed is not published by the oracle authority")]
+    UntrustedPriceFeed,
+    #[msg("Invalid legacy bank account")]
+    InvalidLegacyBank,
+    #[msg("Token account is not a legacy vault")]
+    InvalidLegacyVault,
+    #[msg("Legacy balance has not been swept into the vault")]
+    LegacyAssetsNotSwept,
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use anchor_lang::InstructionData;
+
+    fn bank(authority: Pubkey) -> Bank {
+        Bank {
+            authority,
+            pending_authority: None,
+            paused: false,
+            withdraw_fee_bps: 0,
+            oracle_authority: authority,
+        }
+    }
 
     #[test]
     fn initialize_sets_expected_authority() {
         let authority = Pubkey::new_unique();
-        let bank = Bank { authority };
+        let bank = bank(authority);
+
+        assert_eq!(bank.authority, authority);
+        assert!(!bank.paused);
+    }
+
+    #[test]
+    fn accept_authority_requires_pending_proposal() {
+        let authority = Pubkey::new_unique();
+        let new_authority = Pubkey::new_unique();
+        let mut bank = bank(authority);
+
+        assert!(bank.accept_authority(new_authority).is_err());
 
+        bank.pending_authority = Some(new_authority);
+        assert!(bank.accept_authority(Pubkey::new_unique()).is_err());
         assert_eq!(bank.authority, authority);
+
+        bank.accept_authority(new_authority).unwrap();
+        assert_eq!(bank.authority, new_authority);
+        assert_eq!(bank.pending_authority, None);
+    }
+
+    fn user_account(shares: u64) -> UserAccount {
+        UserAccount {
+            owner: Pubkey::new_unique(),
+            mint: Pubkey::new_unique(),
+            shares,
+            reward_debt: 0,
+            rewards_owed: 0,
+            locked_shares: 0,
+            active_locks: 0,
+        }
+    }
+
+    fn lock_record(schedule: LockSchedule, total_shares: u64) -> LockRecord {
+        let (cliff_ts, end_ts) = schedule.resolve(0).unwrap();
+        LockRecord {
+            user_account: Pubkey::new_unique(),
+            lock_id: 0,
+            total_shares,
+            released_shares: 0,
+            start_ts: 0,
+            cliff_ts,
+            end_ts,
+        }
+    }
+
+    fn vault(total_shares: u64, total_assets: u64) -> Vault {
+        Vault {
+            mint: Pubkey::new_unique(),
+            allowed: true,
+            total_shares,
+            total_assets,
+            legacy_assets: 0,
+            staked_shares: 0,
+            reward_mint: Pubkey::new_unique(),
+            reward_rate: 0,
+            acc_reward_per_share: 0,
+            last_reward_ts: 0,
+            reward_remaining: 0,
+            flash_loan_amount: 0,
+            deposit_cap: 0,
+            max_user_balance: 0,
+            epoch_withdraw_limit: 0,
+            withdraw_epoch: 0,
+            epoch_withdrawn: 0,
+        }
+    }
+
+    fn reward_vault(reward_rate: u64, reward_remaining: u64) -> Vault {
+        Vault {
+            reward_rate,
+            reward_remaining,
+            ..vault(0, 0)
+        }
     }
 
     #[test]
     fn user_account_tracks_token_deposits() {
-        let mut account = UserAccount { deposit_amount: 0 };
+        let mut account = user_account(0);
+
+        account.shares += 500;
+        account.shares += 250;
+
+        assert_eq!(account.shares, 750);
+    }
+
+    #[test]
+    fn empty_vault_mints_shares_one_to_one() {
+        let vault = vault(0, 0);
+
+        assert_eq!(vault.convert_to_shares(1_000, Rounding::Down).unwrap(), 1_000);
+        assert_eq!(vault.convert_to_assets(1_000, Rounding::Down).unwrap(), 1_000);
+    }
+
+    #[test]
+    fn vault_yield_is_shared_by_existing_shares() {
+        // 1000 份额对应 1000 代币，之后金库 ATA 收到 500 代币收益
+        let mut vault = vault(1_000, 1_000);
+        assert_eq!(vault.sync_assets(1_500).unwrap(), 500);
+        assert_eq!(vault.total_assets, 1_500);
+
+        assert_eq!(vault.convert_to_assets(1_000, Rounding::Down).unwrap(), 1_499);
+        assert_eq!(vault.convert_to_shares(1_500, Rounding::Down).unwrap(), 1_000);
+    }
+
+    #[test]
+    fn rounding_favours_the_vault() {
+        let vault = vault(1_000, 1_500);
+
+        // 存款得到的份额向下取整，提取需要销毁的份额向上取整
+        let deposit_shares = vault.convert_to_shares(100, Rounding::Down).unwrap();
+        let withdraw_shares = vault.convert_to_shares(100, Rounding::Up).unwrap();
+        assert_eq!(deposit_shares, 66);
+        assert_eq!(withdraw_shares, 67);
+
+        // 赎回得到的资产不超过存入的资产
+        let redeemed = vault.convert_to_assets(deposit_shares, Rounding::Down).unwrap();
+        assert!(redeemed <= 100);
+    }
+
+    #[test]
+    fn legacy_balances_migrate_one_to_one() {
+        // 两个旧用户各有 100 代币，已转入金库 ATA，但都尚未计入 total_assets
+        let mut vault = vault(0, 0);
+        vault.record_legacy_sweep(200).unwrap();
+
+        assert_eq!(vault.migrate_legacy_balance(100).unwrap(), 100);
+        assert_eq!(vault.migrate_legacy_balance(100).unwrap(), 100);
+        assert_eq!(vault.total_shares, 200);
+        assert_eq!(vault.total_assets, 200);
+        assert_eq!(vault.legacy_assets, 0);
+        assert_eq!(vault.convert_to_assets(100, Rounding::Down).unwrap(), 100);
+    }
+
+    #[test]
+    fn legacy_balance_must_be_swept_before_migration() {
+        let mut vault = vault(1_000, 1_000);
+        vault.record_legacy_sweep(50).unwrap();
+
+        // 只转入了 50，不能按 100 计入份额
+        assert!(vault.migrate_legacy_balance(100).is_err());
+        assert_eq!(vault.total_shares, 1_000);
+        assert_eq!(vault.legacy_assets, 50);
+    }
+
+    #[test]
+    fn legacy_balance_worth_zero_shares_is_rejected() {
+        let mut vault = vault(1, 1_000_001);
+        vault.record_legacy_sweep(100).unwrap();
+
+        assert!(vault.migrate_legacy_balance(100).is_err());
+        assert_eq!(vault.total_shares, 1);
+        assert_eq!(vault.legacy_assets, 100);
+    }
+
+    #[test]
+    fn unaccounted_assets_exclude_shares_and_legacy_balances() {
+        let mut vault = vault(1_000, 1_000);
+        vault.record_legacy_sweep(300).unwrap();
+
+        assert_eq!(vault.unaccounted_assets(1_500), 200);
+        assert_eq!(vault.unaccounted_assets(1_200), 0);
+
+        // 同步收益时待迁移的旧余额不计入
+        assert_eq!(vault.sync_assets(1_500).unwrap(), 200);
+        assert_eq!(vault.total_assets, 1_200);
+        assert_eq!(vault.sync_assets(1_500).unwrap(), 0);
+    }
+
+    #[test]
+    fn donation_attack_is_not_profitable() {
+        // 攻击者先存入 1 个代币得到 1 份额，再直接向金库捐赠大量代币
+        let vault = vault(1, 1_000_001);
+
+        // 受害者存入的代币不足以铸造 1 份额时，应被 ZeroShares 拒绝
+        assert_eq!(vault.convert_to_shares(500_000, Rounding::Down).unwrap(), 0);
+    }
+
+    #[test]
+    fn burn_shares_cannot_underflow() {
+        let mut vault = vault(100, 100);
+
+        assert!(vault.burn_shares(101, 100).is_err());
+        vault.burn_shares(100, 100).unwrap();
+        assert_eq!(vault.total_shares, 0);
+        assert_eq!(vault.total_assets, 0);
+    }
+
+    #[test]
+    fn rewards_are_split_by_staked_shares() {
+        let mut vault = reward_vault(10, 1_000);
+        let mut alice = user_account(0);
+        let mut bob = user_account(0);
+
+        alice.add_shares(&mut vault, 100, 0).unwrap();
+        bob.add_shares(&mut vault, 300, 0).unwrap();
+
+        // 10 秒共发放 100 奖励，按 1:3 分配
+        vault.accrue_rewards(10).unwrap();
+        alice.settle_rewards(vault.acc_reward_per_share).unwrap();
+        bob.settle_rewards(vault.acc_reward_per_share).unwrap();
+
+        assert_eq!(alice.rewards_owed, 25);
+        assert_eq!(bob.rewards_owed, 75);
+        assert_eq!(vault.reward_remaining, 900);
+    }
+
+    #[test]
+    fn deposit_settles_rewards_before_changing_balance() {
+        let mut vault = reward_vault(10, 1_000);
+        let mut alice = user_account(0);
+
+        alice.add_shares(&mut vault, 100, 0).unwrap();
+        // 第 10 秒追加存款，之前的 100 奖励应全部归属原有份额
+        alice.add_shares(&mut vault, 900, 10).unwrap();
+        assert_eq!(alice.rewards_owed, 100);
+
+        // 之后的奖励不会因为新增份额被重复计算
+        vault.accrue_rewards(20).unwrap();
+        alice.settle_rewards(vault.acc_reward_per_share).unwrap();
+        assert_eq!(alice.rewards_owed, 200);
+    }
+
+    #[test]
+    fn late_depositor_does_not_earn_past_rewards() {
+        let mut vault = reward_vault(10, 1_000);
+        let mut alice = user_account(0);
+        let mut bob = user_account(0);
+
+        alice.add_shares(&mut vault, 100, 0).unwrap();
+        bob.add_shares(&mut vault, 100, 10).unwrap();
+        bob.remove_shares(&mut vault, 100, 10).unwrap();
+
+        assert_eq!(bob.rewards_owed, 0);
+        assert_eq!(vault.staked_shares, 100);
+    }
+
+    #[test]
+    fn rewards_are_capped_by_funded_amount() {
+        let mut vault = reward_vault(10, 50);
+        let mut alice = user_account(0);
+
+        alice.add_shares(&mut vault, 100, 0).unwrap();
+        vault.accrue_rewards(100).unwrap();
+        alice.settle_rewards(vault.acc_reward_per_share).unwrap();
+
+        assert_eq!(alice.rewards_owed, 50);
+        assert_eq!(vault.reward_remaining, 0);
+    }
+
+    #[test]
+    fn remove_shares_rejects_insufficient_balance() {
+        let mut vault = reward_vault(0, 0);
+        let mut alice = user_account(0);
+
+        alice.add_shares(&mut vault, 100, 0).unwrap();
+
+        assert!(alice.remove_shares(&mut vault, 101, 0).is_err());
+    }
+
+    #[test]
+    fn flash_loan_fee_rounds_up() {
+        assert_eq!(flash_loan_fee(1_000_000).unwrap(), 900);
+        assert_eq!(flash_loan_fee(1).unwrap(), 1);
+    }
+
+    #[test]
+    fn allowance_is_decremented_until_exhausted() {
+        let mut allowance = Allowance {
+            owner: Pubkey::new_unique(),
+            mint: Pubkey::new_unique(),
+            spender: Pubkey::new_unique(),
+            amount: 100,
+            expires_at: 1_000,
+        };
+
+        allowance.spend(60, 0).unwrap();
+        assert_eq!(allowance.amount, 40);
+        assert!(allowance.spend(41, 0).is_err());
+        allowance.spend(40, 0).unwrap();
+        assert_eq!(allowance.amount, 0);
+    }
+
+    #[test]
+    fn expired_allowance_cannot_be_spent() {
+        let mut allowance = Allowance {
+            owner: Pubkey::new_unique(),
+            mint: Pubkey::new_unique(),
+            spender: Pubkey::new_unique(),
+            amount: 100,
+            expires_at: 1_000,
+        };
+
+        assert!(allowance.spend(1, 1_000).is_err());
+        assert_eq!(allowance.amount, 100);
+    }
+
+    #[test]
+    fn quote_value_scales_between_decimals() {
+        // 2.5 个 9 位精度代币，价格 150.25（expo -2），价值 375.625
+        assert_eq!(quote_value(2_500_000_000, 9, 15_025, -2).unwrap(), 375_625_000);
+        // 6 位精度代币，价格 3（expo 0）
+        assert_eq!(quote_value(1_000_000, 6, 3, 0).unwrap(), 3_000_000);
+        // 不足最小计价单位的部分向下取整
+        assert_eq!(quote_value(1, 9, 1, 0).unwrap(), 0);
+    }
+
+    #[test]
+    fn portfolio_rejects_duplicate_positions() {
+        let (a, b) = (Pubkey::new_unique(), Pubkey::new_unique());
 
-        account.deposit_amount += 500;
-        account.deposit_amount += 250;
+        require_unique_keys(&[a, b]).unwrap();
+        assert!(require_unique_keys(&[a, b, a]).is_err());
+    }
+
+    #[test]
+    fn quote_value_rejects_non_positive_price() {
+        assert!(quote_value(1_000, 6, 0, 0).is_err());
+        assert!(quote_value(1_000, 6, -1, 0).is_err());
+    }
+
+    #[test]
+    fn vault_deposit_cap_and_user_cap_are_enforced() {
+        let mut vault = Vault {
+            deposit_cap: 1_000,
+            max_user_balance: 400,
+            ..vault(0, 0)
+        };
+
+        vault.mint_shares(1_000, 1_000).unwrap();
+        vault.check_deposit_cap().unwrap();
+        vault.mint_shares(1, 1).unwrap();
+        assert!(vault.check_deposit_cap().is_err());
+
+        vault.check_user_balance(400).unwrap();
+        assert!(vault.check_user_balance(401).is_err());
+    }
+
+    #[test]
+    fn vault_epoch_withdraw_limit_resets_each_epoch() {
+        let mut vault = Vault {
+            epoch_withdraw_limit: 500,
+            ..vault(0, 0)
+        };
+
+        vault.record_withdrawal(500, 3).unwrap();
+        assert!(vault.record_withdrawal(1, 3).is_err());
+        vault.record_withdrawal(500, 4).unwrap();
+        assert_eq!(vault.epoch_withdrawn, 500);
+    }
+
+    #[test]
+    fn withdraw_fee_is_capped() {
+        let mut bank = bank(Pubkey::new_unique());
+
+        bank.set_withdraw_fee(MAX_WITHDRAW_FEE_BPS).unwrap();
+        assert!(bank.set_withdraw_fee(MAX_WITHDRAW_FEE_BPS + 1).is_err());
+        assert_eq!(bank.withdraw_fee_bps, MAX_WITHDRAW_FEE_BPS);
+    }
+
+    #[test]
+    fn withdraw_fee_rounds_down() {
+        assert_eq!(withdraw_fee(1_000_000, 30).unwrap(), 3_000);
+        assert_eq!(withdraw_fee(333, 30).unwrap(), 0);
+        assert_eq!(withdraw_fee(1_000, 0).unwrap(), 0);
+    }
+
+    #[test]
+    fn flash_repay_instruction_is_matched_by_vault_and_amount() {
+        let vault = Pubkey::new_unique();
+        let repay = Instruction {
+            program_id: ID,
+            accounts: vec![AccountMeta::new(vault, false)],
+            data: instruction::FlashRepay { amount: 500 }.data(),
+        };
+
+        assert!(is_flash_repay(&repay, &vault, 500));
+        assert!(!is_flash_repay(&repay, &vault, 499));
+        assert!(!is_flash_repay(&repay, &Pubkey::new_unique(), 500));
+
+        let borrow = Instruction {
+            data: instruction::FlashBorrow { amount: 500 }.data(),
+            ..repay
+        };
+        assert!(!is_flash_repay(&borrow, &vault, 500));
+    }
+
+    #[test]
+    fn time_lock_releases_everything_at_unlock_ts() {
+        let lock = lock_record(LockSchedule::Until { unlock_ts: 100 }, 1_000);
+
+        assert_eq!(lock.vested_shares(99).unwrap(), 0);
+        assert_eq!(lock.vested_shares(100).unwrap(), 1_000);
+    }
+
+    #[test]
+    fn vesting_releases_linearly_after_cliff() {
+        let mut lock = lock_record(
+            LockSchedule::Vesting {
+                cliff_seconds: 25,
+                duration_seconds: 100,
+            },
+            1_000,
+        );
+
+        assert_eq!(lock.vested_shares(24).unwrap(), 0);
+        assert_eq!(lock.vested_shares(25).unwrap(), 250);
+        assert_eq!(lock.vested_shares(50).unwrap(), 500);
+        assert_eq!(lock.vested_shares(150).unwrap(), 1_000);
+
+        lock.released_shares = 500;
+        assert_eq!(lock.releasable_shares(75).unwrap(), 250);
+    }
+
+    #[test]
+    fn invalid_lock_schedules_are_rejected() {
+        assert!(LockSchedule::Until { unlock_ts: 10 }.resolve(10).is_err());
+        assert!(LockSchedule::Vesting {
+            cliff_seconds: 101,
+            duration_seconds: 100
+        }
+        .resolve(0)
+        .is_err());
+        assert!(LockSchedule::Vesting {
+            cliff_seconds: 0,
+            duration_seconds: 0
+        }
+        .resolve(0)
+        .is_err());
+    }
+
+    #[test]
+    fn locked_shares_cannot_be_withdrawn() {
+        let mut vault = reward_vault(0, 0);
+        let mut alice = user_account(0);
+
+        alice.add_shares(&mut vault, 1_000, 0).unwrap();
+        alice.locked_shares = 600;
 
-        assert_eq!(account.deposit_amount, 750);
+        assert!(alice.remove_shares(&mut vault, 401, 0).is_err());
+        alice.remove_shares(&mut vault, 400, 0).unwrap();
+        assert_eq!(alice.shares, 600);
     }
 
     #[test]
     fn user_account_can_be_closed_only_when_empty() {
-        let empty_account = UserAccount { deposit_amount: 0 };
-        let non_empty_account = UserAccount { deposit_amount: 1 };
+        let empty_account = user_account(0);
+        let non_empty_account = user_account(1);
 
-        assert_eq!(empty_account.deposit_amount, 0);
-        assert_ne!(non_empty_account.deposit_amount, 0);
+        assert_eq!(empty_account.shares, 0);
+        assert_ne!(non_empty_account.shares, 0);
     }
 
     #[test]
@@ -240,4 +3197,51 @@ mod tests {
 
         assert_eq!(bank_pda.to_string(), "9kGqcfGoHhDBibBE82qr68P4fvP5bMnNm1w5mUHJSH1Q");
     }
+
+    #[test]
+    fn user_pda_is_bound_to_mint() {
+        let owner = Pubkey::new_unique();
+        let mint_a = Pubkey::new_unique();
+        let mint_b = Pubkey::new_unique();
+
+        let (user_a, _) =
+            Pubkey::find_program_address(&[b"user", owner.as_ref(), mint_a.as_ref()], &ID);
+        let (user_b, _) =
+            Pubkey::find_program_address(&[b"user", owner.as_ref(), mint_b.as_ref()], &ID);
+
+        assert_ne!(user_a, user_b);
+    }
+
+    #[test]
+    fn legacy_bank_data_is_parsed() {
+        let authority = Pubkey::new_unique();
+        let mut data = Bank::DISCRIMINATOR.to_vec();
+        data.extend_from_slice(authority.as_ref());
+
+        assert_eq!(legacy_bank_authority(&data).unwrap(), authority);
+    }
+
+    #[test]
+    fn legacy_bank_rejects_new_layout() {
+        let mut data = Vec::new();
+        bank(Pubkey::new_unique()).try_serialize(&mut data).unwrap();
+
+        assert!(legacy_bank_authority(&data).is_err());
+    }
+
+    #[test]
+    fn legacy_user_account_data_is_parsed() {
+        let mut data = UserAccount::DISCRIMINATOR.to_vec();
+        data.extend_from_slice(&750u64.to_le_bytes());
+
+        assert_eq!(legacy_deposit_amount(&data).unwrap(), 750);
+    }
+
+    #[test]
+    fn legacy_user_account_rejects_new_layout() {
+        let mut data = Vec::new();
+        user_account(750).try_serialize(&mut data).unwrap();
+
+        assert!(legacy_deposit_amount(&data).is_err());
+    }
 }
@@ -0,0 +1,988 @@
+use std::{fs, path::PathBuf};
+
+use anchor_lang::{
+    prelude::{Clock, Pubkey as AnchorPubkey},
+    solana_program::system_instruction,
+    AccountDeserialize, Discriminator, InstructionData, ToAccountMetas,
+};
+use anchor_spl::{associated_token, token::spl_token};
+use litesvm::{types::TransactionMetadata, LiteSVM};
+use solana_account::Account;
+use solana_instruction::{AccountMeta, Instruction};
+use solana_keypair::Keypair;
+use solana_pubkey::Pubkey;
+use solana_sdk_ids::{system_program, sysvar};
+use solana_signer::Signer;
+use solana_transaction::Transaction;
+use tokenbank::{
+    accounts, flash_loan_fee, instruction, UserAccount, Vault, ID as TOKENBANK_PROGRAM_ID,
+};
+
+const MINT_DECIMALS: u8 = 6;
+const TOKEN_ACCOUNT_LEN: usize = 165;
+const MINT_LEN: usize = 82;
+
+fn program_binary(name: &str) -> PathBuf {
+    let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    path.push(format!("../../target/deploy/{name}.so"));
+    path
+}
+
+fn to_pubkey(pubkey: AnchorPubkey) -> Pubkey {
+    Pubkey::from(pubkey.to_bytes())
+}
+
+fn to_anchor_pubkey(pubkey: &Pubkey) -> AnchorPubkey {
+    AnchorPubkey::new_from_array(pubkey.to_bytes())
+}
+
+fn to_account_meta(meta: anchor_lang::prelude::AccountMeta) -> AccountMeta {
+    if meta.is_writable {
+        AccountMeta::new(Pubkey::from(meta.pubkey.to_bytes()), meta.is_signer)
+    } else {
+        AccountMeta::new_readonly(Pubkey::from(meta.pubkey.to_bytes()), meta.is_signer)
+    }
+}
+
+fn tokenbank_instruction(accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+    program_instruction(TOKENBANK_PROGRAM_ID, accounts, data)
+}
+
+fn program_instruction(
+    program_id: AnchorPubkey,
+    accounts: impl ToAccountMetas,
+    data: impl InstructionData,
+) -> Instruction {
+    Instruction {
+        program_id: to_pubkey(program_id),
+        accounts: accounts
+            .to_account_metas(None)
+            .into_iter()
+            .map(to_account_meta)
+            .collect(),
+        data: data.data(),
+    }
+}
+
+// remaining_accounts 依次拼接每一组账户
+fn batch_instruction<T: ToAccountMetas>(
+    accounts: impl ToAccountMetas,
+    groups: &[T],
+    data: impl InstructionData,
+) -> Instruction {
+    let mut instruction = tokenbank_instruction(accounts, data);
+    instruction.accounts.extend(
+        groups
+            .iter()
+            .flat_map(|group| group.to_account_metas(None))
+            .map(to_account_meta),
+    );
+    instruction
+}
+
+fn find_pda(seeds: &[&[u8]]) -> Pubkey {
+    Pubkey::find_program_address(seeds, &to_pubkey(TOKENBANK_PROGRAM_ID)).0
+}
+
+fn user_account(owner: &Pubkey, mint: &Pubkey) -> Pubkey {
+    find_pda(&[b"user", owner.as_ref(), mint.as_ref()])
+}
+
+fn tokenbank_ata(bank: &Pubkey, mint: &Pubkey) -> Pubkey {
+    to_pubkey(
+        associated_token::get_associated_token_address_with_program_id(
+            &to_anchor_pubkey(bank),
+            &to_anchor_pubkey(mint),
+            &spl_token::ID,
+        ),
+    )
+}
+
+// 已初始化 bank、注册 mint，并由一个存款人存入流动性的测试环境
+struct TestEnv {
+    svm: LiteSVM,
+    payer: Keypair,
+    mint: Pubkey,
+    bank: Pubkey,
+    vault: Pubkey,
+    tokenbank_ata: Pubkey,
+}
+
+impl TestEnv {
+    fn new(liquidity: u64) -> Self {
+        let mut svm = LiteSVM::new();
+        let payer = Keypair::new();
+
+        let program_bytes = fs::read(program_binary("tokenbank")).expect("read tokenbank.so");
+        svm.add_program(to_pubkey(TOKENBANK_PROGRAM_ID), &program_bytes)
+            .expect("load tokenbank program");
+        svm.airdrop(&payer.pubkey(), 10_000_000_000)
+            .expect("airdrop payer");
+
+        let bank = find_pda(&[b"bank"]);
+        let mut env = TestEnv {
+            svm,
+            payer,
+            mint: Pubkey::default(),
+            bank,
+            vault: Pubkey::default(),
+            tokenbank_ata: Pubkey::default(),
+        };
+
+        let initialize = tokenbank_instruction(
+            accounts::Initialize {
+                bank: to_anchor_pubkey(&bank),
+                authority: to_anchor_pubkey(&env.payer.pubkey()),
+                system_program: system_program::ID,
+            },
+            instruction::Initialize {},
+        );
+        env.send(&[initialize], &[]).expect("initialize bank");
+
+        env.mint = env.create_mint();
+        let (vault, tokenbank_ata) = env.register_mint(&env.mint.clone());
+        env.vault = vault;
+        env.tokenbank_ata = tokenbank_ata;
+
+        let depositor = Keypair::new();
+        env.svm
+            .airdrop(&depositor.pubkey(), 1_000_000_000)
+            .expect("airdrop depositor");
+        let mint = env.mint;
+        let depositor_ata = env.create_token_account(&depositor.pubkey(), liquidity);
+        let create_user_account = env.create_user_account(&depositor, &mint);
+        let deposit = tokenbank_instruction(
+            env.deposit_accounts(&depositor, &mint, &depositor_ata),
+            instruction::Deposit { amount: liquidity },
+        );
+        env.send(&[create_user_account, deposit], &[&depositor])
+            .expect("deposit liquidity");
+
+        env
+    }
+
+    fn send(
+        &mut self,
+        instructions: &[Instruction],
+        signers: &[&Keypair],
+    ) -> Result<TransactionMetadata, Vec<String>> {
+        let mut all_signers = vec![&self.payer];
+        all_signers.extend_from_slice(signers);
+
+        let tx = Transaction::new_signed_with_payer(
+            instructions,
+            Some(&self.payer.pubkey()),
+            &all_signers,
+            self.svm.latest_blockhash(),
+        );
+        // 失败时返回日志，便于断言具体的错误码
+        let result = self.svm.send_transaction(tx).map_err(|err| err.meta.logs);
+        self.svm.expire_blockhash();
+        result
+    }
+
+    // 创建 payer 为 mint authority 的 SPL Token mint
+    fn create_mint(&mut self) -> Pubkey {
+        let mint = Keypair::new();
+        let mint_rent = self.svm.minimum_balance_for_rent_exemption(MINT_LEN);
+        let create_mint = [
+            system_instruction::create_account(
+                &self.payer.pubkey(),
+                &mint.pubkey(),
+                mint_rent,
+                MINT_LEN as u64,
+                &spl_token::ID,
+            ),
+            spl_token::instruction::initialize_mint2(
+                &spl_token::ID,
+                &mint.pubkey(),
+                &self.payer.pubkey(),
+                None,
+                MINT_DECIMALS,
+            )
+            .expect("initialize_mint2 instruction"),
+        ];
+        self.send(&create_mint, &[&mint]).expect("create mint");
+        mint.pubkey()
+    }
+
+    // 注册 mint，返回 (vault, tokenbank_ata)
+    fn register_mint(&mut self, mint: &Pubkey) -> (Pubkey, Pubkey) {
+        let vault = find_pda(&[b"vault", mint.as_ref()]);
+        let tokenbank_ata = tokenbank_ata(&self.bank, mint);
+        let register_mint = tokenbank_instruction(
+            accounts::RegisterMint {
+                bank: to_anchor_pubkey(&self.bank),
+                vault: to_anchor_pubkey(&vault),
+                mint: to_anchor_pubkey(mint),
+                tokenbank_ata: to_anchor_pubkey(&tokenbank_ata),
+                treasury: to_anchor_pubkey(&find_pda(&[b"treasury", mint.as_ref()])),
+                authority: to_anchor_pubkey(&self.payer.pubkey()),
+                payer: to_anchor_pubkey(&self.payer.pubkey()),
+                token_program: spl_token::ID,
+                associated_token_program: associated_token::ID,
+                system_program: system_program::ID,
+            },
+            instruction::RegisterMint {},
+        );
+        self.send(&[register_mint], &[]).expect("register mint");
+        (vault, tokenbank_ata)
+    }
+
+    fn create_user_account(&self, owner: &Keypair, mint: &Pubkey) -> Instruction {
+        tokenbank_instruction(
+            accounts::CreateUserAccount {
+                user_account: to_anchor_pubkey(&user_account(&owner.pubkey(), mint)),
+                mint: to_anchor_pubkey(mint),
+                owner: to_anchor_pubkey(&owner.pubkey()),
+                system_program: system_program::ID,
+            },
+            instruction::CreateUserAccount {},
+        )
+    }
+
+    fn deposit_accounts(
+        &self,
+        depositor: &Keypair,
+        mint: &Pubkey,
+        depositor_ata: &Pubkey,
+    ) -> accounts::Deposit {
+        accounts::Deposit {
+            bank: to_anchor_pubkey(&self.bank),
+            vault: to_anchor_pubkey(&find_pda(&[b"vault", mint.as_ref()])),
+            user_account: to_anchor_pubkey(&user_account(&depositor.pubkey(), mint)),
+            mint: to_anchor_pubkey(mint),
+            depositor_ata: to_anchor_pubkey(depositor_ata),
+            tokenbank_ata: to_anchor_pubkey(&tokenbank_ata(&self.bank, mint)),
+            depositor: to_anchor_pubkey(&depositor.pubkey()),
+            payer: to_anchor_pubkey(&self.payer.pubkey()),
+            token_program: spl_token::ID,
+            system_program: system_program::ID,
+        }
+    }
+
+    fn withdraw_accounts(
+        &self,
+        receiver: &Keypair,
+        mint: &Pubkey,
+        receiver_ata: &Pubkey,
+    ) -> accounts::Withdraw {
+        accounts::Withdraw {
+            bank: to_anchor_pubkey(&self.bank),
+            vault: to_anchor_pubkey(&find_pda(&[b"vault", mint.as_ref()])),
+            user_account: to_anchor_pubkey(&user_account(&receiver.pubkey(), mint)),
+            mint: to_anchor_pubkey(mint),
+            tokenbank_ata: to_anchor_pubkey(&tokenbank_ata(&self.bank, mint)),
+            receiver_ata: to_anchor_pubkey(receiver_ata),
+            treasury: to_anchor_pubkey(&find_pda(&[b"treasury", mint.as_ref()])),
+            receiver: to_anchor_pubkey(&receiver.pubkey()),
+            token_program: spl_token::ID,
+        }
+    }
+
+    // 创建 owner 持有的代币账户，并铸造 amount 个代币
+    fn create_token_account(&mut self, owner: &Pubkey, amount: u64) -> Pubkey {
+        let mint = self.mint;
+        self.create_token_account_for(&mint, owner, amount)
+    }
+
+    fn create_token_account_for(&mut self, mint: &Pubkey, owner: &Pubkey, amount: u64) -> Pubkey {
+        let account = Keypair::new();
+        let rent = self
+            .svm
+            .minimum_balance_for_rent_exemption(TOKEN_ACCOUNT_LEN);
+        let mut instructions = vec![
+            system_instruction::create_account(
+                &self.payer.pubkey(),
+                &account.pubkey(),
+                rent,
+                TOKEN_ACCOUNT_LEN as u64,
+                &spl_token::ID,
+            ),
+            spl_token::instruction::initialize_account3(
+                &spl_token::ID,
+                &account.pubkey(),
+                mint,
+                owner,
+            )
+            .expect("initialize_account3 instruction"),
+        ];
+        if amount > 0 {
+            instructions.push(
+                spl_token::instruction::mint_to(
+                    &spl_token::ID,
+                    mint,
+                    &account.pubkey(),
+                    &self.payer.pubkey(),
+                    &[],
+                    amount,
+                )
+                .expect("mint_to instruction"),
+            );
+        }
+
+        self.send(&instructions, &[&account])
+            .expect("create token account");
+        account.pubkey()
+    }
+
+    fn token_balance(&self, token_account: &Pubkey) -> u64 {
+        let account = self
+            .svm
+            .get_account(token_account)
+            .expect("token account should exist");
+        let mut amount = [0u8; 8];
+        // SPL token account 布局：mint(32) + owner(32) + amount(8)
+        amount.copy_from_slice(&account.data[64..72]);
+        u64::from_le_bytes(amount)
+    }
+
+    fn vault_state(&self) -> Vault {
+        let account = self
+            .svm
+            .get_account(&self.vault)
+            .expect("vault account should exist");
+        let mut data = account.data.as_slice();
+        Vault::try_deserialize(&mut data).expect("deserialize vault account")
+    }
+
+    fn user_shares(&self, owner: &Pubkey, mint: &Pubkey) -> u64 {
+        let account = self
+            .svm
+            .get_account(&user_account(owner, mint))
+            .expect("user account should exist");
+        let mut data = account.data.as_slice();
+        UserAccount::try_deserialize(&mut data)
+            .expect("deserialize user account")
+            .shares
+    }
+
+    // 新建 count 个已注册的 mint，并为 owner 创建账本和持有 amount 个代币的账户
+    fn setup_mints(&mut self, owner: &Keypair, count: usize, amount: u64) -> Vec<(Pubkey, Pubkey)> {
+        self.svm
+            .airdrop(&owner.pubkey(), 1_000_000_000)
+            .expect("airdrop owner");
+        (0..count)
+            .map(|_| {
+                let mint = self.create_mint();
+                self.register_mint(&mint);
+                let owner_ata = self.create_token_account_for(&mint, &owner.pubkey(), amount);
+                let create_user_account = self.create_user_account(owner, &mint);
+                self.send(&[create_user_account], &[owner])
+                    .expect("create user account");
+                (mint, owner_ata)
+            })
+            .collect()
+    }
+
+    // 加载 mock_oracle 并由 bank authority 为 mint 创建价格账户
+    fn create_price_feed(&mut self, mint: &Pubkey, price: i64, expo: i32) -> Pubkey {
+        let authority = self.payer.insecure_clone();
+        self.create_price_feed_by(&authority, mint, price, expo)
+    }
+
+    fn create_price_feed_by(
+        &mut self,
+        authority: &Keypair,
+        mint: &Pubkey,
+        price: i64,
+        expo: i32,
+    ) -> Pubkey {
+        if self.svm.get_account(&to_pubkey(mock_oracle::ID)).is_none() {
+            let program_bytes =
+                fs::read(program_binary("mock_oracle")).expect("read mock_oracle.so");
+            self.svm
+                .add_program(to_pubkey(mock_oracle::ID), &program_bytes)
+                .expect("load mock_oracle program");
+        }
+
+        let price_feed = Pubkey::find_program_address(
+            &[b"price", authority.pubkey().as_ref(), mint.as_ref()],
+            &to_pubkey(mock_oracle::ID),
+        )
+        .0;
+        let initialize_feed = program_instruction(
+            mock_oracle::ID,
+            mock_oracle::accounts::InitializeFeed {
+                price_feed: to_anchor_pubkey(&price_feed),
+                mint: to_anchor_pubkey(mint),
+                authority: to_anchor_pubkey(&authority.pubkey()),
+                system_program: system_program::ID,
+            },
+            mock_oracle::instruction::InitializeFeed {
+                price,
+                expo,
+                max_staleness: 60,
+            },
+        );
+        let signers: &[&Keypair] = if authority.pubkey() == self.payer.pubkey() {
+            &[]
+        } else {
+            &[authority]
+        };
+        self.send(&[initialize_feed], signers)
+            .expect("initialize price feed");
+        price_feed
+    }
+
+    fn get_portfolio_value(
+        &mut self,
+        owner: &Pubkey,
+        positions: &[(Pubkey, Pubkey)],
+    ) -> Result<u64, Vec<String>> {
+        let mut instruction = tokenbank_instruction(
+            accounts::GetPortfolioValue {
+                bank: to_anchor_pubkey(&self.bank),
+                owner: to_anchor_pubkey(owner),
+            },
+            instruction::GetPortfolioValue {},
+        );
+        for (mint, price_feed) in positions {
+            instruction.accounts.extend([
+                AccountMeta::new_readonly(user_account(owner, mint), false),
+                AccountMeta::new_readonly(find_pda(&[b"vault", mint.as_ref()]), false),
+                AccountMeta::new_readonly(*mint, false),
+                AccountMeta::new_readonly(*price_feed, false),
+            ]);
+        }
+
+        let meta = self.send(&[instruction], &[])?;
+        let mut value = [0u8; 8];
+        value.copy_from_slice(&meta.return_data.data);
+        Ok(u64::from_le_bytes(value))
+    }
+
+    // 写入旧版本的 UserAccount（discriminator + deposit_amount），seeds 只包含 owner
+    fn create_legacy_user_account(&mut self, owner: &Pubkey, deposit_amount: u64) -> Pubkey {
+        let legacy_user_account = find_pda(&[b"user", owner.as_ref()]);
+        let mut data = UserAccount::DISCRIMINATOR.to_vec();
+        data.extend_from_slice(&deposit_amount.to_le_bytes());
+        self.svm
+            .set_account(
+                legacy_user_account,
+                Account {
+                    lamports: self.svm.minimum_balance_for_rent_exemption(data.len()),
+                    data,
+                    owner: to_pubkey(TOKENBANK_PROGRAM_ID),
+                    executable: false,
+                    rent_epoch: 0,
+                },
+            )
+            .expect("set legacy user account");
+        legacy_user_account
+    }
+
+    fn sweep_legacy_vault(&mut self, legacy_vault: Option<Pubkey>) -> Result<(), Vec<String>> {
+        let sweep = tokenbank_instruction(
+            accounts::SweepLegacyVault {
+                bank: to_anchor_pubkey(&self.bank),
+                vault: to_anchor_pubkey(&self.vault),
+                mint: to_anchor_pubkey(&self.mint),
+                legacy_vault: legacy_vault.as_ref().map(to_anchor_pubkey),
+                tokenbank_ata: to_anchor_pubkey(&self.tokenbank_ata),
+                authority: to_anchor_pubkey(&self.payer.pubkey()),
+                token_program: spl_token::ID,
+            },
+            instruction::SweepLegacyVault {},
+        );
+        self.send(&[sweep], &[]).map(|_| ())
+    }
+
+    fn migrate_user_account(&mut self, owner: &Pubkey) -> Result<(), Vec<String>> {
+        let migrate = tokenbank_instruction(
+            accounts::MigrateUserAccount {
+                bank: to_anchor_pubkey(&self.bank),
+                legacy_user_account: to_anchor_pubkey(&find_pda(&[b"user", owner.as_ref()])),
+                user_account: to_anchor_pubkey(&user_account(owner, &self.mint)),
+                vault: to_anchor_pubkey(&self.vault),
+                mint: to_anchor_pubkey(&self.mint),
+                owner: to_anchor_pubkey(owner),
+                authority: to_anchor_pubkey(&self.payer.pubkey()),
+                payer: to_anchor_pubkey(&self.payer.pubkey()),
+                token_program: spl_token::ID,
+                system_program: system_program::ID,
+            },
+            instruction::MigrateUserAccount {},
+        );
+        self.send(&[migrate], &[]).map(|_| ())
+    }
+
+    // 把 amount 个代币直接转入金库 ATA，不经过 deposit
+    fn pay_yield(&mut self, amount: u64) {
+        let yield_source = self.create_token_account(&self.payer.pubkey(), amount);
+        let transfer = spl_token::instruction::transfer(
+            &spl_token::ID,
+            &yield_source,
+            &self.tokenbank_ata,
+            &self.payer.pubkey(),
+            &[],
+            amount,
+        )
+        .expect("transfer instruction");
+        self.send(&[transfer], &[]).expect("pay yield");
+    }
+
+    fn sync_assets(&mut self) -> Result<TransactionMetadata, Vec<String>> {
+        let sync = tokenbank_instruction(
+            accounts::SyncAssets {
+                bank: to_anchor_pubkey(&self.bank),
+                vault: to_anchor_pubkey(&self.vault),
+                mint: to_anchor_pubkey(&self.mint),
+                tokenbank_ata: to_anchor_pubkey(&self.tokenbank_ata),
+                authority: to_anchor_pubkey(&self.payer.pubkey()),
+                token_program: spl_token::ID,
+            },
+            instruction::SyncAssets {},
+        );
+        self.send(&[sync], &[])
+    }
+
+    fn preview_withdraw(&mut self, amount: u64) -> u64 {
+        let preview = tokenbank_instruction(
+            accounts::Preview {
+                bank: to_anchor_pubkey(&self.bank),
+                vault: to_anchor_pubkey(&self.vault),
+                mint: to_anchor_pubkey(&self.mint),
+                token_program: spl_token::ID,
+            },
+            instruction::PreviewWithdraw { amount },
+        );
+        let meta = self.send(&[preview], &[]).expect("preview withdraw");
+        let mut shares = [0u8; 8];
+        shares.copy_from_slice(&meta.return_data.data);
+        u64::from_le_bytes(shares)
+    }
+
+    fn flash_borrow(&self, borrower: &Keypair, borrower_ata: &Pubkey, amount: u64) -> Instruction {
+        tokenbank_instruction(
+            accounts::FlashBorrow {
+                bank: to_anchor_pubkey(&self.bank),
+                vault: to_anchor_pubkey(&self.vault),
+                mint: to_anchor_pubkey(&self.mint),
+                tokenbank_ata: to_anchor_pubkey(&self.tokenbank_ata),
+                borrower_ata: to_anchor_pubkey(borrower_ata),
+                borrower: to_anchor_pubkey(&borrower.pubkey()),
+                token_program: spl_token::ID,
+                instructions: sysvar::instructions::ID,
+            },
+            instruction::FlashBorrow { amount },
+        )
+    }
+
+    fn flash_repay(&self, repayer: &Keypair, repayer_ata: &Pubkey, amount: u64) -> Instruction {
+        tokenbank_instruction(
+            accounts::FlashRepay {
+                vault: to_anchor_pubkey(&self.vault),
+                mint: to_anchor_pubkey(&self.mint),
+                bank: to_anchor_pubkey(&self.bank),
+                tokenbank_ata: to_anchor_pubkey(&self.tokenbank_ata),
+                repayer_ata: to_anchor_pubkey(repayer_ata),
+                repayer: to_anchor_pubkey(&repayer.pubkey()),
+                token_program: spl_token::ID,
+            },
+            instruction::FlashRepay { amount },
+        )
+    }
+}
+
+#[test]
+fn flash_loan_repaid_in_same_transaction_pays_fee_to_vault() {
+    let mut env = TestEnv::new(1_000_000);
+    let borrower = Keypair::new();
+    let amount = 500_000;
+    let fee = flash_loan_fee(amount).unwrap();
+    // 借款人只需要预先持有手续费
+    let borrower_ata = env.create_token_account(&borrower.pubkey(), fee);
+
+    let borrow = env.flash_borrow(&borrower, &borrower_ata, amount);
+    let repay = env.flash_repay(&borrower, &borrower_ata, amount);
+    env.send(&[borrow, repay], &[&borrower])
+        .expect("borrow and repay flash loan");
+
+    assert_eq!(env.token_balance(&env.tokenbank_ata), 1_000_000 + fee);
+    assert_eq!(env.token_balance(&borrower_ata), 0);
+    assert_eq!(env.vault_state().flash_loan_amount, 0);
+    // 手续费计入总资产，由存款人共享
+    assert_eq!(env.vault_state().total_assets, 1_000_000 + fee);
+}
+
+#[test]
+fn flash_loan_without_repay_is_rejected() {
+    let mut env = TestEnv::new(1_000_000);
+    let borrower = Keypair::new();
+    let borrower_ata = env.create_token_account(&borrower.pubkey(), 0);
+
+    let borrow = env.flash_borrow(&borrower, &borrower_ata, 500_000);
+    let logs = env
+        .send(&[borrow], &[&borrower])
+        .expect_err("unrepaid flash loan should fail");
+
+    assert!(logs.iter().any(|log| log.contains("MissingFlashRepay")));
+    assert_eq!(env.token_balance(&env.tokenbank_ata), 1_000_000);
+}
+
+#[test]
+fn flash_loan_with_smaller_repay_is_rejected() {
+    let mut env = TestEnv::new(1_000_000);
+    let borrower = Keypair::new();
+    let borrower_ata = env.create_token_account(&borrower.pubkey(), 1_000);
+
+    let borrow = env.flash_borrow(&borrower, &borrower_ata, 500_000);
+    let repay = env.flash_repay(&borrower, &borrower_ata, 400_000);
+    let logs = env
+        .send(&[borrow, repay], &[&borrower])
+        .expect_err("repay with a different amount should fail");
+
+    assert!(logs.iter().any(|log| log.contains("MissingFlashRepay")));
+}
+
+#[test]
+fn nested_flash_loan_is_rejected() {
+    let mut env = TestEnv::new(1_000_000);
+    let borrower = Keypair::new();
+    let amount = 200_000;
+    let fee = flash_loan_fee(amount).unwrap();
+    let borrower_ata = env.create_token_account(&borrower.pubkey(), fee * 2);
+
+    let instructions = [
+        env.flash_borrow(&borrower, &borrower_ata, amount),
+        env.flash_borrow(&borrower, &borrower_ata, amount),
+        env.flash_repay(&borrower, &borrower_ata, amount),
+        env.flash_repay(&borrower, &borrower_ata, amount),
+    ];
+    let logs = env
+        .send(&instructions, &[&borrower])
+        .expect_err("nested flash loan should fail");
+
+    assert!(logs.iter().any(|log| log.contains("FlashLoanActive")));
+    assert_eq!(env.token_balance(&env.tokenbank_ata), 1_000_000);
+    assert_eq!(env.vault_state().flash_loan_amount, 0);
+}
+
+#[test]
+fn first_deposit_creates_user_account_paid_by_sponsor() {
+    let mut env = TestEnv::new(1_000_000);
+    let mint = env.create_mint();
+    env.register_mint(&mint);
+    // owner 没有 SOL，租金和手续费都由 env.payer 支付
+    let owner = Keypair::new();
+    let owner_ata = env.create_token_account_for(&mint, &owner.pubkey(), 1_000);
+
+    for _ in 0..2 {
+        let deposit = tokenbank_instruction(
+            env.deposit_accounts(&owner, &mint, &owner_ata),
+            instruction::Deposit { amount: 400 },
+        );
+        env.send(&[deposit], &[&owner]).expect("deposit");
+    }
+
+    let account = env
+        .svm
+        .get_account(&user_account(&owner.pubkey(), &mint))
+        .expect("user account should exist");
+    let user = UserAccount::try_deserialize(&mut account.data.as_slice())
+        .expect("deserialize user account");
+    assert_eq!(to_pubkey(user.owner), owner.pubkey());
+    assert_eq!(to_pubkey(user.mint), mint);
+    // 第二次存款沿用已有账户，没有被重新初始化
+    assert_eq!(user.shares, 800);
+    assert_eq!(env.svm.get_balance(&owner.pubkey()).unwrap_or(0), 0);
+}
+
+#[test]
+fn batch_deposit_and_withdraw_cover_every_mint() {
+    let mut env = TestEnv::new(1_000_000);
+    let owner = Keypair::new();
+    let mints = env.setup_mints(&owner, 3, 1_000);
+
+    let deposits: Vec<_> = mints
+        .iter()
+        .map(|(mint, ata)| env.deposit_accounts(&owner, mint, ata))
+        .collect();
+    let batch_deposit = batch_instruction(
+        accounts::BatchDeposit {
+            depositor: to_anchor_pubkey(&owner.pubkey()),
+        },
+        &deposits,
+        instruction::BatchDeposit {
+            amounts: vec![100, 200, 300],
+        },
+    );
+    env.send(&[batch_deposit], &[&owner])
+        .expect("batch deposit");
+
+    for ((mint, ata), expected) in mints.iter().zip([100, 200, 300]) {
+        assert_eq!(env.user_shares(&owner.pubkey(), mint), expected);
+        assert_eq!(env.token_balance(ata), 1_000 - expected);
+    }
+
+    let withdraws: Vec<_> = mints
+        .iter()
+        .map(|(mint, ata)| env.withdraw_accounts(&owner, mint, ata))
+        .collect();
+    let batch_withdraw = batch_instruction(
+        accounts::BatchWithdraw {
+            receiver: to_anchor_pubkey(&owner.pubkey()),
+        },
+        &withdraws,
+        instruction::BatchWithdraw {
+            amounts: vec![100, 100, 100],
+        },
+    );
+    env.send(&[batch_withdraw], &[&owner])
+        .expect("batch withdraw");
+
+    for ((mint, _), expected) in mints.iter().zip([0, 100, 200]) {
+        assert_eq!(env.user_shares(&owner.pubkey(), mint), expected);
+    }
+}
+
+#[test]
+fn batch_deposit_is_atomic() {
+    let mut env = TestEnv::new(1_000_000);
+    let owner = Keypair::new();
+    let mints = env.setup_mints(&owner, 2, 1_000);
+
+    let mut deposits: Vec<_> = mints
+        .iter()
+        .map(|(mint, ata)| env.deposit_accounts(&owner, mint, ata))
+        .collect();
+    // 第二组使用 bank 持有的非标准金库账户，应与单笔 deposit 一样被拒绝
+    let spoofed_vault = env.create_token_account_for(&mints[1].0, &env.bank.clone(), 0);
+    deposits[1].tokenbank_ata = to_anchor_pubkey(&spoofed_vault);
+
+    let batch_deposit = batch_instruction(
+        accounts::BatchDeposit {
+            depositor: to_anchor_pubkey(&owner.pubkey()),
+        },
+        &deposits,
+        instruction::BatchDeposit {
+            amounts: vec![100, 100],
+        },
+    );
+    let logs = env
+        .send(&[batch_deposit], &[&owner])
+        .expect_err("batch with a spoofed vault should fail");
+
+    assert!(logs
+        .iter()
+        .any(|log| log.contains("AccountNotAssociatedTokenAccount")));
+    assert_eq!(env.user_shares(&owner.pubkey(), &mints[0].0), 0);
+    assert_eq!(env.token_balance(&mints[0].1), 1_000);
+}
+
+#[test]
+fn batch_deposit_uses_fewer_compute_units_than_separate_deposits() {
+    const MINTS: usize = 4;
+    let mut env = TestEnv::new(1_000_000);
+    let owner = Keypair::new();
+    let mints = env.setup_mints(&owner, MINTS, 1_000);
+    let deposits: Vec<_> = mints
+        .iter()
+        .map(|(mint, ata)| env.deposit_accounts(&owner, mint, ata))
+        .collect();
+
+    let mut separate_units = 0;
+    for (mint, ata) in &mints {
+        let deposit = tokenbank_instruction(
+            env.deposit_accounts(&owner, mint, ata),
+            instruction::Deposit { amount: 100 },
+        );
+        separate_units += env
+            .send(&[deposit], &[&owner])
+            .expect("single deposit")
+            .compute_units_consumed;
+    }
+
+    let batch_deposit = batch_instruction(
+        accounts::BatchDeposit {
+            depositor: to_anchor_pubkey(&owner.pubkey()),
+        },
+        &deposits,
+        instruction::BatchDeposit {
+            amounts: vec![100; MINTS],
+        },
+    );
+    let batch_units = env
+        .send(&[batch_deposit], &[&owner])
+        .expect("batch deposit")
+        .compute_units_consumed;
+
+    assert!(
+        batch_units < separate_units,
+        "batch_deposit used {batch_units} CU, separate deposits used {separate_units} CU"
+    );
+    for (mint, _) in &mints {
+        assert_eq!(env.user_shares(&owner.pubkey(), mint), 200);
+    }
+}
+
+#[test]
+fn portfolio_value_sums_positions_at_oracle_prices() {
+    let mut env = TestEnv::new(1_000_000);
+    let owner = Keypair::new();
+    let mints = env.setup_mints(&owner, 2, 10_000);
+    let deposits: Vec<_> = mints
+        .iter()
+        .map(|(mint, ata)| env.deposit_accounts(&owner, mint, ata))
+        .collect();
+    let batch_deposit = batch_instruction(
+        accounts::BatchDeposit {
+            depositor: to_anchor_pubkey(&owner.pubkey()),
+        },
+        &deposits,
+        instruction::BatchDeposit {
+            amounts: vec![1_000, 1_000],
+        },
+    );
+    env.send(&[batch_deposit], &[&owner])
+        .expect("batch deposit");
+
+    // 价格 2.5 和 4，两笔持仓都是 0.001 个代币
+    let first_feed = env.create_price_feed(&mints[0].0, 2_500_000, -6);
+    let second_feed = env.create_price_feed(&mints[1].0, 4, 0);
+    let positions = [(mints[0].0, first_feed), (mints[1].0, second_feed)];
+
+    let value = env
+        .get_portfolio_value(&owner.pubkey(), &positions)
+        .expect("portfolio value");
+    assert_eq!(value, 2_500 + 4_000);
+}
+
+#[test]
+fn portfolio_value_rejects_duplicate_positions() {
+    let mut env = TestEnv::new(1_000_000);
+    let owner = Keypair::new();
+    let mints = env.setup_mints(&owner, 1, 10_000);
+    let price_feed = env.create_price_feed(&mints[0].0, 1, 0);
+    let position = (mints[0].0, price_feed);
+
+    let logs = env
+        .get_portfolio_value(&owner.pubkey(), &[position, position])
+        .expect_err("duplicate position should be rejected");
+    assert!(logs
+        .iter()
+        .any(|log| log.contains("DuplicatePortfolioAccount")));
+}
+
+#[test]
+fn portfolio_value_rejects_untrusted_price_feed() {
+    let mut env = TestEnv::new(1_000_000);
+    let owner = Keypair::new();
+    let mints = env.setup_mints(&owner, 1, 10_000);
+
+    // 任何人都能为同一个 mint 发布价格，只有 oracle_authority 的价格被采用
+    let attacker = Keypair::new();
+    env.svm
+        .airdrop(&attacker.pubkey(), 1_000_000_000)
+        .expect("airdrop attacker");
+    let price_feed = env.create_price_feed_by(&attacker, &mints[0].0, 1_000_000, 0);
+
+    let logs = env
+        .get_portfolio_value(&owner.pubkey(), &[(mints[0].0, price_feed)])
+        .expect_err("untrusted price feed should be rejected");
+    assert!(logs.iter().any(|log| log.contains("UntrustedPriceFeed")));
+}
+
+#[test]
+fn portfolio_value_rejects_stale_price() {
+    let mut env = TestEnv::new(1_000_000);
+    let owner = Keypair::new();
+    let mints = env.setup_mints(&owner, 1, 10_000);
+    let price_feed = env.create_price_feed(&mints[0].0, 1, 0);
+
+    let mut clock = env.svm.get_sysvar::<Clock>();
+    clock.unix_timestamp += 61;
+    env.svm.set_sysvar(&clock);
+
+    let logs = env
+        .get_portfolio_value(&owner.pubkey(), &[(mints[0].0, price_feed)])
+        .expect_err("stale price should be rejected");
+    assert!(logs.iter().any(|log| log.contains("StalePrice")));
+}
+
+#[test]
+fn legacy_balance_is_credited_only_after_sweep() {
+    let mut env = TestEnv::new(1_000_000);
+    let owner = Pubkey::new_unique();
+    env.create_legacy_user_account(&owner, 400);
+    // 旧版本的金库是 bank 拥有的普通代币账户
+    let bank = env.bank;
+    let legacy_vault = env.create_token_account(&bank, 400);
+
+    let logs = env
+        .migrate_user_account(&owner)
+        .expect_err("migration before sweep should fail");
+    assert!(logs.iter().any(|log| log.contains("LegacyAssetsNotSwept")));
+
+    env.sweep_legacy_vault(Some(legacy_vault))
+        .expect("sweep legacy vault");
+    assert_eq!(env.token_balance(&legacy_vault), 0);
+    assert_eq!(env.token_balance(&env.tokenbank_ata), 1_000_400);
+    assert_eq!(env.vault_state().legacy_assets, 400);
+
+    env.migrate_user_account(&owner)
+        .expect("migrate legacy user");
+    let vault = env.vault_state();
+    assert_eq!(env.user_shares(&owner, &env.mint.clone()), 400);
+    assert_eq!(vault.legacy_assets, 0);
+    assert_eq!(vault.total_assets, 1_000_400);
+}
+
+#[test]
+fn synced_yield_is_shared_by_depositors() {
+    let mut env = TestEnv::new(1_000_000);
+    // 收益直接转入金库 ATA，同步前不计入 total_assets
+    env.pay_yield(500_000);
+    assert_eq!(env.vault_state().total_assets, 1_000_000);
+
+    env.sync_assets().expect("sync assets");
+
+    let vault = env.vault_state();
+    assert_eq!(vault.total_assets, 1_500_000);
+    assert_eq!(vault.total_shares, 1_000_000);
+}
+
+#[test]
+fn preview_withdraw_matches_withdraw() {
+    let mut env = TestEnv::new(1_000_000);
+    let mint = env.mint;
+    let user = Keypair::new();
+    env.svm
+        .airdrop(&user.pubkey(), 1_000_000_000)
+        .expect("airdrop user");
+    let user_ata = env.create_token_account(&user.pubkey(), 300_000);
+    let deposit = tokenbank_instruction(
+        env.deposit_accounts(&user, &mint, &user_ata),
+        instruction::Deposit { amount: 300_000 },
+    );
+    env.send(&[deposit], &[&user]).expect("deposit");
+
+    // 同步收益并收取手续费，份额价格不再是 1:1
+    env.pay_yield(500_000);
+    env.sync_assets().expect("sync assets");
+    let set_fee = tokenbank_instruction(
+        accounts::AdminOnly {
+            bank: to_anchor_pubkey(&env.bank),
+            authority: to_anchor_pubkey(&env.payer.pubkey()),
+        },
+        instruction::SetWithdrawFee { fee_bps: 30 },
+    );
+    env.send(&[set_fee], &[]).expect("set withdraw fee");
+
+    let amount = 100_000;
+    let expected_shares = env.preview_withdraw(amount);
+    let shares_before = env.user_shares(&user.pubkey(), &mint);
+    let withdraw = tokenbank_instruction(
+        env.withdraw_accounts(&user, &mint, &user_ata),
+        instruction::Withdraw { amount },
+    );
+    env.send(&[withdraw], &[&user]).expect("withdraw");
+
+    assert_eq!(
+        shares_before - env.user_shares(&user.pubkey(), &mint),
+        expected_shares
+    );
+    // amount 为手续费前的数量，实际到账扣除 30 bps
+    assert_eq!(env.token_balance(&user_ata), amount - 300);
+}
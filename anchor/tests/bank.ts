@@ -10,6 +10,38 @@ describe("bank", () => {
 
   const program = anchor.workspace.Bank as Program<Bank>;
 
+  const [statePDA] = PublicKey.findProgramAddressSync(
+    [Buffer.from("state")],
+    program.programId
+  );
+
+  it("初始化银行状态", async () => {
+    const [bankPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("bank")],
+      program.programId
+    );
+
+    try {
+      await program.methods
+        .initialize()
+        .accounts({
+          bank: bankPDA,
+          state: statePDA,
+          admin: provider.wallet.publicKey,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .rpc();
+    } catch (error: any) {
+      // 状态账户已存在时只验证 admin
+      if (!error.message?.includes("already in use")) {
+        throw error;
+      }
+    }
+
+    const state = await program.account.bankState.fetch(statePDA);
+    assert.equal(state.admin.toBase58(), provider.wallet.publicKey.toBase58());
+  });
+
   it("创建用户账户", async () => {
     const [userPDA] = PublicKey.findProgramAddressSync(
       [Buffer.from("user"), provider.wallet.publicKey.toBuffer()],
@@ -21,6 +53,7 @@ describe("bank", () => {
         .createUserAccount()
         .accounts({
           userAccount: userPDA,
+          state: statePDA,
           owner: provider.wallet.publicKey,
           systemProgram: anchor.web3.SystemProgram.programId,
         })
@@ -76,8 +109,10 @@ describe("bank", () => {
       .deposit(depositAmount)
       .accounts({
         bank: bankPDA,
+        state: statePDA,
         userAccount: userPDA,
         depositor: provider.wallet.publicKey,
+        payer: provider.wallet.publicKey,
         systemProgram: anchor.web3.SystemProgram.programId,
       })
       .rpc();
@@ -120,6 +155,7 @@ describe("bank", () => {
       .withdraw(withdrawAmount)
       .accounts({
         bank: bankPDA,
+        state: statePDA,
         userAccount: userPDA,
         receiver: provider.wallet.publicKey,
         systemProgram: anchor.web3.SystemProgram.programId,
@@ -135,6 +171,94 @@ describe("bank", () => {
     assert.equal(userAccount.depositAmount.toNumber(), currentDeposit - withdrawAmount.toNumber());
   });
 
+  it("提取到第三方收款账户", async () => {
+    const [bankPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("bank")],
+      program.programId
+    );
+    const [userPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("user"), provider.wallet.publicKey.toBuffer()],
+      program.programId
+    );
+
+    // 收款账户无需签名，也无需事先存在
+    const recipient = Keypair.generate();
+    const withdrawAmount = new anchor.BN(100_000_000); // 0.1 SOL
+    const userAccountBefore = await program.account.userAccount.fetch(userPDA);
+
+    await program.methods
+      .withdrawTo(withdrawAmount)
+      .accounts({
+        bank: bankPDA,
+        state: statePDA,
+        userAccount: userPDA,
+        owner: provider.wallet.publicKey,
+        recipient: recipient.publicKey,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      })
+      .rpc();
+
+    const userAccount = await program.account.userAccount.fetch(userPDA);
+    assert.equal(
+      await provider.connection.getBalance(recipient.publicKey),
+      withdrawAmount.toNumber()
+    );
+    assert.equal(
+      userAccount.depositAmount.toNumber(),
+      userAccountBefore.depositAmount.toNumber() - withdrawAmount.toNumber()
+    );
+  });
+
+  it("用户之间的账本转账", async () => {
+    const [bankPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("bank")],
+      program.programId
+    );
+    const [userPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("user"), provider.wallet.publicKey.toBuffer()],
+      program.programId
+    );
+
+    // 收款方还没有 UserAccount，会在转账时创建
+    const to = Keypair.generate().publicKey;
+    const [toUserPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("user"), to.toBuffer()],
+      program.programId
+    );
+    const amount = new anchor.BN(100_000_000);
+
+    const userAccountBefore = await program.account.userAccount.fetch(userPDA);
+    const stateBefore = await program.account.bankState.fetch(statePDA);
+    const bankBefore = await provider.connection.getBalance(bankPDA);
+
+    for (let i = 0; i < 2; i++) {
+      await program.methods
+        .transferInternal(to, amount)
+        .accounts({
+          state: statePDA,
+          userAccount: userPDA,
+          toUserAccount: toUserPDA,
+          owner: provider.wallet.publicKey,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .rpc();
+    }
+
+    const userAccount = await program.account.userAccount.fetch(userPDA);
+    const toUserAccount = await program.account.userAccount.fetch(toUserPDA);
+    const stateAfter = await program.account.bankState.fetch(statePDA);
+
+    assert.equal(toUserAccount.depositAmount.toNumber(), 2 * amount.toNumber());
+    assert.equal(
+      userAccount.depositAmount.toNumber(),
+      userAccountBefore.depositAmount.toNumber() - 2 * amount.toNumber()
+    );
+    // lamports 与存款总额不变，只新增一个用户
+    assert.equal(await provider.connection.getBalance(bankPDA), bankBefore);
+    assert.equal(stateAfter.totalDeposits.toNumber(), stateBefore.totalDeposits.toNumber());
+    assert.equal(stateAfter.userCount.toNumber(), stateBefore.userCount.toNumber() + 1);
+  });
+
   it("在一个交易中创建用户账户并存款", async () => {
     // 创建一个新的用户密钥对
     const newUser = Keypair.generate();
@@ -161,6 +285,7 @@ describe("bank", () => {
       .createUserAccount()
       .accounts({
         userAccount: userPDA,
+        state: statePDA,
         owner: newUser.publicKey,
         systemProgram: anchor.web3.SystemProgram.programId,
       })
@@ -172,8 +297,10 @@ describe("bank", () => {
       .deposit(depositAmount)
       .accounts({
         bank: bankPDA,
+        state: statePDA,
         userAccount: userPDA,
         depositor: newUser.publicKey,
+        payer: newUser.publicKey,
         systemProgram: anchor.web3.SystemProgram.programId,
       })
       .instruction();
@@ -181,6 +308,7 @@ describe("bank", () => {
     // 创建并发送包含两个指令的交易
     const tx = new Transaction().add(createUserIx).add(depositIx);
     const initialBalance = await provider.connection.getBalance(bankPDA);
+    const stateBefore = await program.account.bankState.fetch(statePDA);
 
     await provider.sendAndConfirm(tx, [newUser]);
 
@@ -190,5 +318,559 @@ describe("bank", () => {
 
     assert.equal(finalBalance - initialBalance, depositAmount.toNumber());
     assert.equal(userAccount.depositAmount.toNumber(), depositAmount.toNumber());
+
+    // 全局状态同步更新，无需扫描所有 UserAccount
+    const stateAfter = await program.account.bankState.fetch(statePDA);
+    assert.equal(stateAfter.userCount.toNumber(), stateBefore.userCount.toNumber() + 1);
+    assert.equal(
+      stateAfter.totalDeposits.toNumber(),
+      stateBefore.totalDeposits.toNumber() + depositAmount.toNumber()
+    );
+  });
+
+  it("超过单用户余额上限的存款被拒绝", async () => {
+    const [bankPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("bank")],
+      program.programId
+    );
+    const [userPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("user"), provider.wallet.publicKey.toBuffer()],
+      program.programId
+    );
+    const userAccount = await program.account.userAccount.fetch(userPDA);
+
+    await program.methods
+      .setLimits(new anchor.BN(0), userAccount.depositAmount, new anchor.BN(0))
+      .accounts({
+        state: statePDA,
+        admin: provider.wallet.publicKey,
+      })
+      .rpc();
+
+    try {
+      await program.methods
+        .deposit(new anchor.BN(1))
+        .accounts({
+          bank: bankPDA,
+          state: statePDA,
+          userAccount: userPDA,
+          depositor: provider.wallet.publicKey,
+          payer: provider.wallet.publicKey,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .rpc();
+      assert.fail("超过上限的存款应被拒绝");
+    } catch (error: any) {
+      assert.include(error.toString(), "UserBalanceCapExceeded");
+    } finally {
+      await program.methods
+        .setLimits(new anchor.BN(0), new anchor.BN(0), new anchor.BN(0))
+        .accounts({
+          state: statePDA,
+          admin: provider.wallet.publicKey,
+        })
+        .rpc();
+    }
+  });
+
+  it("bank 余额扣除 reserve 后足以覆盖存款总额", async () => {
+    const [bankPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("bank")],
+      program.programId
+    );
+
+    await program.methods
+      .checkSolvency()
+      .accounts({
+        bank: bankPDA,
+        state: statePDA,
+      })
+      .rpc();
+
+    const state = await program.account.bankState.fetch(statePDA);
+    const reserve =
+      await provider.connection.getMinimumBalanceForRentExemption(0);
+    const bankBalance = await provider.connection.getBalance(bankPDA);
+    assert.isAtLeast(
+      bankBalance - reserve,
+      state.totalDeposits.toNumber() +
+        state.interestPool.toNumber() +
+        state.pendingWithdrawals.toNumber()
+    );
+  });
+
+  it("余额为零时关闭用户账户", async () => {
+    const user = Keypair.generate();
+    const airdropSig = await provider.connection.requestAirdrop(
+      user.publicKey,
+      1_000_000_000
+    );
+    await provider.connection.confirmTransaction(airdropSig);
+
+    const [userPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("user"), user.publicKey.toBuffer()],
+      program.programId
+    );
+
+    await program.methods
+      .createUserAccount()
+      .accounts({
+        userAccount: userPDA,
+        state: statePDA,
+        owner: user.publicKey,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      })
+      .signers([user])
+      .rpc();
+
+    const stateBefore = await program.account.bankState.fetch(statePDA);
+    const balanceBefore = await provider.connection.getBalance(user.publicKey);
+
+    await program.methods
+      .closeUserAccount()
+      .accounts({
+        userAccount: userPDA,
+        state: statePDA,
+        owner: user.publicKey,
+      })
+      .signers([user])
+      .rpc();
+
+    // 租金退还给 owner
+    assert.isNull(await provider.connection.getAccountInfo(userPDA));
+    assert.isAbove(await provider.connection.getBalance(user.publicKey), balanceBefore);
+    const stateAfter = await program.account.bankState.fetch(statePDA);
+    assert.equal(stateAfter.userCount.toNumber(), stateBefore.userCount.toNumber() - 1);
+  });
+
+  it("取出全部存款并关闭用户账户", async () => {
+    const user = Keypair.generate();
+    const airdropSig = await provider.connection.requestAirdrop(
+      user.publicKey,
+      2_000_000_000
+    );
+    await provider.connection.confirmTransaction(airdropSig);
+
+    const [bankPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("bank")],
+      program.programId
+    );
+    const [userPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("user"), user.publicKey.toBuffer()],
+      program.programId
+    );
+
+    const depositAmount = new anchor.BN(500_000_000);
+    await program.methods
+      .createUserAccount()
+      .accounts({
+        userAccount: userPDA,
+        state: statePDA,
+        owner: user.publicKey,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      })
+      .postInstructions([
+        await program.methods
+          .deposit(depositAmount)
+          .accounts({
+            bank: bankPDA,
+            state: statePDA,
+            userAccount: userPDA,
+            depositor: user.publicKey,
+            payer: user.publicKey,
+            systemProgram: anchor.web3.SystemProgram.programId,
+          })
+          .instruction(),
+      ])
+      .signers([user])
+      .rpc();
+
+    // 有余额时不能直接关闭
+    try {
+      await program.methods
+        .closeUserAccount()
+        .accounts({
+          userAccount: userPDA,
+          state: statePDA,
+          owner: user.publicKey,
+        })
+        .signers([user])
+        .rpc();
+      assert.fail("有余额的账户不应被关闭");
+    } catch (error: any) {
+      assert.include(error.toString(), "AccountNotEmpty");
+    }
+
+    const bankBefore = await provider.connection.getBalance(bankPDA);
+    const stateBefore = await program.account.bankState.fetch(statePDA);
+
+    await program.methods
+      .withdrawAllAndClose()
+      .accounts({
+        bank: bankPDA,
+        state: statePDA,
+        userAccount: userPDA,
+        owner: user.publicKey,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      })
+      .signers([user])
+      .rpc();
+
+    assert.isNull(await provider.connection.getAccountInfo(userPDA));
+    assert.equal(
+      bankBefore - (await provider.connection.getBalance(bankPDA)),
+      depositAmount.toNumber()
+    );
+    const stateAfter = await program.account.bankState.fetch(statePDA);
+    assert.equal(stateAfter.userCount.toNumber(), stateBefore.userCount.toNumber() - 1);
+    assert.equal(
+      stateBefore.totalDeposits.toNumber() - stateAfter.totalDeposits.toNumber(),
+      depositAmount.toNumber()
+    );
+  });
+
+  it("admin 注入利息池，存取款时结算利息", async () => {
+    const [bankPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("bank")],
+      program.programId
+    );
+    const [userPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("user"), provider.wallet.publicKey.toBuffer()],
+      program.programId
+    );
+    const fundAmount = new anchor.BN(1_000_000);
+
+    await program.methods
+      .fundInterest(fundAmount)
+      .accounts({
+        bank: bankPDA,
+        state: statePDA,
+        admin: provider.wallet.publicKey,
+        payer: provider.wallet.publicKey,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      })
+      .rpc();
+    await program.methods
+      .setInterestRate(10_000) // 100% APR
+      .accounts({
+        state: statePDA,
+        admin: provider.wallet.publicKey,
+      })
+      .rpc();
+
+    try {
+      const stateBefore = await program.account.bankState.fetch(statePDA);
+      const userBefore = await program.account.userAccount.fetch(userPDA);
+
+      // 等待若干 slot 让利息累计
+      await new Promise((resolve) => setTimeout(resolve, 2_000));
+
+      const withdrawAmount = new anchor.BN(1);
+      await program.methods
+        .withdraw(withdrawAmount)
+        .accounts({
+          bank: bankPDA,
+          state: statePDA,
+          userAccount: userPDA,
+          receiver: provider.wallet.publicKey,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .rpc();
+
+      const stateAfter = await program.account.bankState.fetch(statePDA);
+      const userAfter = await program.account.userAccount.fetch(userPDA);
+      const paid = stateBefore.interestPool.sub(stateAfter.interestPool).toNumber();
+
+      assert.isAbove(paid, 0);
+      assert.equal(
+        userAfter.depositAmount.toNumber(),
+        userBefore.depositAmount.toNumber() + paid - withdrawAmount.toNumber()
+      );
+
+      // 利息池仍计入 bank 余额
+      await program.methods
+        .checkSolvency()
+        .accounts({
+          bank: bankPDA,
+          state: statePDA,
+        })
+        .rpc();
+    } finally {
+      await program.methods
+        .setInterestRate(0)
+        .accounts({
+          state: statePDA,
+          admin: provider.wallet.publicKey,
+        })
+        .rpc();
+    }
+  });
+
+  it("大额提款需要延迟执行，可以取消", async () => {
+    const [bankPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("bank")],
+      program.programId
+    );
+    const [userPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("user"), provider.wallet.publicKey.toBuffer()],
+      program.programId
+    );
+    const pendingPDA = (requestId: anchor.BN) =>
+      PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("pending"),
+          provider.wallet.publicKey.toBuffer(),
+          requestId.toArrayLike(Buffer, "le", 8),
+        ],
+        program.programId
+      )[0];
+    const setWithdrawDelay = (threshold: number, delay: number) =>
+      program.methods
+        .setWithdrawDelay(new anchor.BN(threshold), new anchor.BN(delay))
+        .accounts({
+          state: statePDA,
+          admin: provider.wallet.publicKey,
+        })
+        .rpc();
+
+    const amount = new anchor.BN(100_000_000);
+    await setWithdrawDelay(amount.toNumber(), 3600);
+
+    try {
+      // 达到阈值的即时提款被拒绝
+      try {
+        await program.methods
+          .withdraw(amount)
+          .accounts({
+            bank: bankPDA,
+            state: statePDA,
+            userAccount: userPDA,
+            receiver: provider.wallet.publicKey,
+            systemProgram: anchor.web3.SystemProgram.programId,
+          })
+          .rpc();
+        assert.fail("大额即时提款应被拒绝");
+      } catch (error: any) {
+        assert.include(error.toString(), "WithdrawalRequiresDelay");
+      }
+
+      const requestId = new anchor.BN(Date.now());
+      const userBefore = await program.account.userAccount.fetch(userPDA);
+      await program.methods
+        .requestWithdraw(requestId, amount)
+        .accounts({
+          state: statePDA,
+          userAccount: userPDA,
+          pendingWithdrawal: pendingPDA(requestId),
+          owner: provider.wallet.publicKey,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .rpc();
+
+      // 申请后余额立即被扣除，不能再次提取
+      const userPending = await program.account.userAccount.fetch(userPDA);
+      assert.isAtMost(
+        userPending.depositAmount.toNumber(),
+        userBefore.depositAmount.toNumber() - amount.toNumber() + 1_000
+      );
+
+      try {
+        await program.methods
+          .executeWithdraw(requestId)
+          .accounts({
+            bank: bankPDA,
+            state: statePDA,
+            pendingWithdrawal: pendingPDA(requestId),
+            owner: provider.wallet.publicKey,
+            systemProgram: anchor.web3.SystemProgram.programId,
+          })
+          .rpc();
+        assert.fail("未到解锁时间不能执行");
+      } catch (error: any) {
+        assert.include(error.toString(), "WithdrawalLocked");
+      }
+
+      await program.methods
+        .cancelWithdraw(requestId)
+        .accounts({
+          state: statePDA,
+          userAccount: userPDA,
+          pendingWithdrawal: pendingPDA(requestId),
+          owner: provider.wallet.publicKey,
+        })
+        .rpc();
+      const userCancelled = await program.account.userAccount.fetch(userPDA);
+      assert.isAtLeast(
+        userCancelled.depositAmount.toNumber(),
+        userBefore.depositAmount.toNumber()
+      );
+      assert.isNull(await provider.connection.getAccountInfo(pendingPDA(requestId)));
+
+      // 延迟为 0 时申请后即可执行
+      await setWithdrawDelay(amount.toNumber(), 0);
+      const executeId = requestId.addn(1);
+      await program.methods
+        .requestWithdraw(executeId, amount)
+        .accounts({
+          state: statePDA,
+          userAccount: userPDA,
+          pendingWithdrawal: pendingPDA(executeId),
+          owner: provider.wallet.publicKey,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .rpc();
+
+      const bankBefore = await provider.connection.getBalance(bankPDA);
+      await program.methods
+        .executeWithdraw(executeId)
+        .accounts({
+          bank: bankPDA,
+          state: statePDA,
+          pendingWithdrawal: pendingPDA(executeId),
+          owner: provider.wallet.publicKey,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .rpc();
+      assert.equal(
+        bankBefore - (await provider.connection.getBalance(bankPDA)),
+        amount.toNumber()
+      );
+
+      // 窗口内的即时提款按用户累计，拆成多笔也不能绕过阈值
+      await setWithdrawDelay(2_000, 3600);
+      const withdrawSmall = (lamports: number) =>
+        program.methods
+          .withdraw(new anchor.BN(lamports))
+          .accounts({
+            bank: bankPDA,
+            state: statePDA,
+            userAccount: userPDA,
+            receiver: provider.wallet.publicKey,
+            systemProgram: anchor.web3.SystemProgram.programId,
+          })
+          .rpc();
+      await withdrawSmall(1_500);
+      try {
+        await withdrawSmall(1_000);
+        assert.fail("累计超过阈值的即时提款应被拒绝");
+      } catch (error: any) {
+        assert.include(error.toString(), "WithdrawalRequiresDelay");
+      }
+
+      // 转给新账户同样计入窗口，不能借新账户的额度绕过阈值
+      const to = Keypair.generate().publicKey;
+      try {
+        await program.methods
+          .transferInternal(to, new anchor.BN(1_000))
+          .accounts({
+            state: statePDA,
+            userAccount: userPDA,
+            toUserAccount: PublicKey.findProgramAddressSync(
+              [Buffer.from("user"), to.toBuffer()],
+              program.programId
+            )[0],
+            owner: provider.wallet.publicKey,
+            systemProgram: anchor.web3.SystemProgram.programId,
+          })
+          .rpc();
+        assert.fail("累计超过阈值的转账应被拒绝");
+      } catch (error: any) {
+        assert.include(error.toString(), "WithdrawalRequiresDelay");
+      }
+    } finally {
+      await setWithdrawDelay(0, 0);
+    }
+  });
+
+  it("代他人存款", async () => {
+    const [bankPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("bank")],
+      program.programId
+    );
+
+    // 受益人无需签名，也无需事先创建 UserAccount
+    const beneficiary = Keypair.generate().publicKey;
+    const [beneficiaryPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("user"), beneficiary.toBuffer()],
+      program.programId
+    );
+    const amount = new anchor.BN(200_000_000);
+    const stateBefore = await program.account.bankState.fetch(statePDA);
+
+    const tx = await program.methods
+      .depositFor(beneficiary, amount)
+      .accounts({
+        bank: bankPDA,
+        state: statePDA,
+        userAccount: beneficiaryPDA,
+        payer: provider.wallet.publicKey,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      })
+      .rpc({ commitment: "confirmed" });
+
+    const userAccount = await program.account.userAccount.fetch(beneficiaryPDA);
+    const stateAfter = await program.account.bankState.fetch(statePDA);
+    assert.equal(userAccount.depositAmount.toNumber(), amount.toNumber());
+    assert.equal(stateAfter.userCount.toNumber(), stateBefore.userCount.toNumber() + 1);
+
+    // 事件同时记录出资方和受益人
+    const txInfo = await provider.connection.getParsedTransaction(tx, "confirmed");
+    const events = (txInfo?.meta?.logMessages ?? [])
+      .filter((log) => log.startsWith("Program data: "))
+      .map((log) => program.coder.events.decode(log.slice("Program data: ".length)))
+      .filter((event) => event?.name === "depositForEvent");
+    assert.equal(events.length, 1);
+    assert.equal(events[0].data.payer.toBase58(), provider.wallet.publicKey.toBase58());
+    assert.equal(events[0].data.beneficiary.toBase58(), beneficiary.toBase58());
+    assert.equal(events[0].data.amount.toNumber(), amount.toNumber());
+  });
+
+  it("首次存款时自动创建用户账户，由赞助方支付租金", async () => {
+    const user = Keypair.generate();
+    const airdropSig = await provider.connection.requestAirdrop(
+      user.publicKey,
+      1_000_000_000
+    );
+    await provider.connection.confirmTransaction(airdropSig);
+
+    const [bankPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("bank")],
+      program.programId
+    );
+    const [userPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("user"), user.publicKey.toBuffer()],
+      program.programId
+    );
+    const amount = new anchor.BN(300_000_000);
+    const stateBefore = await program.account.bankState.fetch(statePDA);
+
+    const deposit = () =>
+      program.methods
+        .deposit(amount)
+        .accounts({
+          bank: bankPDA,
+          state: statePDA,
+          userAccount: userPDA,
+          depositor: user.publicKey,
+          payer: provider.wallet.publicKey,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+
+    await deposit();
+    const userBalance = await provider.connection.getBalance(user.publicKey);
+    // 第二次存款不会重新初始化账户
+    await deposit();
+
+    const userAccount = await program.account.userAccount.fetch(userPDA);
+    const stateAfter = await program.account.bankState.fetch(statePDA);
+    assert.equal(userAccount.depositAmount.toNumber(), 2 * amount.toNumber());
+    assert.equal(stateAfter.userCount.toNumber(), stateBefore.userCount.toNumber() + 1);
+    // 租金和手续费都由赞助方支付，用户只转出存款
+    assert.equal(
+      userBalance - (await provider.connection.getBalance(user.publicKey)),
+      amount.toNumber()
+    );
   });
-}); 
+});
@@ -0,0 +1,355 @@
+import * as anchor from "@anchor-lang/core";
+import { Program } from "@anchor-lang/core";
+import { Bank } from "../target/types/bank";
+import { Multisig } from "../target/types/multisig";
+import {
+  PublicKey,
+  Keypair,
+  SystemProgram,
+  TransactionInstruction,
+} from "@solana/web3.js";
+import {
+  TOKEN_PROGRAM_ID,
+  ASSOCIATED_TOKEN_PROGRAM_ID,
+  createMint,
+  getAssociatedTokenAddressSync,
+} from "@solana/spl-token";
+import { assert } from "chai";
+
+describe("multisig", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Multisig as Program<Multisig>;
+  const bankProgram = anchor.workspace.Bank as Program<Bank>;
+  const tokenbankProgram = anchor.workspace.tokenbank;
+
+  const [statePDA] = PublicKey.findProgramAddressSync(
+    [Buffer.from("state")],
+    bankProgram.programId
+  );
+
+  const member2 = Keypair.generate();
+  const member3 = Keypair.generate();
+  const createKey = Keypair.generate().publicKey;
+  const [multisigPDA] = PublicKey.findProgramAddressSync(
+    [Buffer.from("multisig"), createKey.toBuffer()],
+    program.programId
+  );
+  // 无数据的 vault PDA 作为提案指令的 payer
+  const [vaultPDA] = PublicKey.findProgramAddressSync(
+    [Buffer.from("vault"), multisigPDA.toBuffer()],
+    program.programId
+  );
+
+  const proposalPDA = (index: number) =>
+    PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("proposal"),
+        multisigPDA.toBuffer(),
+        new anchor.BN(index).toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    )[0];
+
+  // 把指令写入提案，返回提案地址和执行时需要的 remaining_accounts
+  const propose = async (ix: TransactionInstruction) => {
+    const multisig = await program.account.multisig.fetch(multisigPDA);
+    const proposal = proposalPDA(multisig.proposalCount.toNumber());
+    const expiresAt = new anchor.BN(Math.floor(Date.now() / 1000) + 3600);
+
+    await program.methods
+      .propose(
+        ix.programId,
+        ix.keys.map((key) => ({
+          pubkey: key.pubkey,
+          isSigner: key.isSigner,
+          isWritable: key.isWritable,
+        })),
+        ix.data,
+        expiresAt
+      )
+      .accounts({
+        multisig: multisigPDA,
+        proposal,
+        proposer: provider.wallet.publicKey,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      })
+      .rpc();
+
+    const remainingAccounts = [
+      ...ix.keys.map((key) => ({
+        pubkey: key.pubkey,
+        isWritable: key.isWritable,
+        // 多签 PDA 和 vault PDA 由程序签名，交易层面不需要签名
+        isSigner: false,
+      })),
+      { pubkey: ix.programId, isWritable: false, isSigner: false },
+    ];
+    return { proposal, remainingAccounts };
+  };
+
+  const approve = (proposal: PublicKey, member: Keypair) =>
+    program.methods
+      .approve()
+      .accounts({
+        multisig: multisigPDA,
+        proposal,
+        member: member.publicKey,
+      })
+      .signers([member])
+      .rpc();
+
+  const execute = (proposal: PublicKey, remainingAccounts: any[]) =>
+    program.methods
+      .execute()
+      .accounts({
+        multisig: multisigPDA,
+        proposal,
+        member: provider.wallet.publicKey,
+      })
+      .remainingAccounts(remainingAccounts)
+      .rpc();
+
+  it("创建 2-of-3 多签", async () => {
+    await program.methods
+      .createMultisig(
+        createKey,
+        [provider.wallet.publicKey, member2.publicKey, member3.publicKey],
+        2
+      )
+      .accounts({
+        multisig: multisigPDA,
+        payer: provider.wallet.publicKey,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      })
+      .rpc();
+
+    const multisig = await program.account.multisig.fetch(multisigPDA);
+    assert.equal(multisig.members.length, 3);
+    assert.equal(multisig.threshold, 2);
+  });
+
+  it("提案中只有多签 PDA 可以作为签名者", async () => {
+    // 借用执行成员的签名调用 bank admin 指令
+    const ix = await bankProgram.methods
+      .setLimits(new anchor.BN(1), new anchor.BN(0), new anchor.BN(0))
+      .accounts({
+        state: statePDA,
+        admin: provider.wallet.publicKey,
+      })
+      .instruction();
+
+    try {
+      await propose(ix);
+      assert.fail("非多签签名者的提案应被拒绝");
+    } catch (error: any) {
+      assert.include(error.toString(), "UnauthorizedSigner");
+    }
+  });
+
+  it("多签接管 bank admin 并交还", async () => {
+    const originalAdmin = provider.wallet.publicKey;
+
+    await bankProgram.methods
+      .proposeAdmin(multisigPDA)
+      .accounts({
+        state: statePDA,
+        admin: originalAdmin,
+      })
+      .rpc();
+
+    const acceptIx = await bankProgram.methods
+      .acceptAdmin()
+      .accounts({
+        state: statePDA,
+        newAdmin: multisigPDA,
+      })
+      .instruction();
+    const accept = await propose(acceptIx);
+
+    // 只有提案人的批准，未达到阈值
+    try {
+      await execute(accept.proposal, accept.remainingAccounts);
+      assert.fail("未达到阈值不能执行");
+    } catch (error: any) {
+      assert.include(error.toString(), "ThresholdNotMet");
+    }
+
+    await approve(accept.proposal, member2);
+    await execute(accept.proposal, accept.remainingAccounts);
+
+    let state = await bankProgram.account.bankState.fetch(statePDA);
+    assert.equal(state.admin.toBase58(), multisigPDA.toBase58());
+
+    // 已执行的提案不能重复执行
+    try {
+      await execute(accept.proposal, accept.remainingAccounts);
+      assert.fail("提案不能重复执行");
+    } catch (error: any) {
+      assert.include(error.toString(), "AlreadyExecuted");
+    }
+
+    // 单个成员不能再直接调用 admin 指令
+    try {
+      await bankProgram.methods
+        .setLimits(new anchor.BN(1), new anchor.BN(0), new anchor.BN(0))
+        .accounts({
+          state: statePDA,
+          admin: originalAdmin,
+        })
+        .rpc();
+      assert.fail("原 admin 已失去权限");
+    } catch (error: any) {
+      assert.include(error.toString(), "Unauthorized");
+    }
+
+    // 通过提案把 admin 交还，恢复其他测试依赖的状态
+    const proposeIx = await bankProgram.methods
+      .proposeAdmin(originalAdmin)
+      .accounts({
+        state: statePDA,
+        admin: multisigPDA,
+      })
+      .instruction();
+    const handBack = await propose(proposeIx);
+    await approve(handBack.proposal, member3);
+    await execute(handBack.proposal, handBack.remainingAccounts);
+
+    await bankProgram.methods
+      .acceptAdmin()
+      .accounts({
+        state: statePDA,
+        newAdmin: originalAdmin,
+      })
+      .rpc();
+
+    state = await bankProgram.account.bankState.fetch(statePDA);
+    assert.equal(state.admin.toBase58(), originalAdmin.toBase58());
+
+    // 已执行的提案可以关闭，租金退还给提案人
+    await program.methods
+      .closeProposal()
+      .accounts({
+        proposal: accept.proposal,
+        proposer: originalAdmin,
+      })
+      .rpc();
+    assert.isNull(await provider.connection.getAccountInfo(accept.proposal));
+  });
+
+  it("多签接管 tokenbank authority，由 vault 支付注册 mint 的租金", async () => {
+    const originalAuthority = provider.wallet.publicKey;
+    const [tokenbankPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("bank")],
+      tokenbankProgram.programId
+    );
+
+    try {
+      await tokenbankProgram.methods
+        .initialize()
+        .accounts({
+          bank: tokenbankPDA,
+          authority: originalAuthority,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+    } catch (error: any) {
+      if (!error.message?.includes("already in use")) {
+        throw error;
+      }
+    }
+
+    await tokenbankProgram.methods
+      .proposeAuthority(multisigPDA)
+      .accounts({
+        bank: tokenbankPDA,
+        authority: originalAuthority,
+      })
+      .rpc();
+
+    const acceptIx = await tokenbankProgram.methods
+      .acceptAuthority()
+      .accounts({
+        bank: tokenbankPDA,
+        newAuthority: multisigPDA,
+      })
+      .instruction();
+    const accept = await propose(acceptIx);
+    await approve(accept.proposal, member2);
+    await execute(accept.proposal, accept.remainingAccounts);
+
+    let bank = await tokenbankProgram.account.bank.fetch(tokenbankPDA);
+    assert.equal(bank.authority.toBase58(), multisigPDA.toBase58());
+
+    // 多签 PDA 存有数据不能支付租金，先给 vault 充值
+    await provider.sendAndConfirm(
+      new anchor.web3.Transaction().add(
+        SystemProgram.transfer({
+          fromPubkey: originalAuthority,
+          toPubkey: vaultPDA,
+          lamports: anchor.web3.LAMPORTS_PER_SOL,
+        })
+      )
+    );
+
+    const mint = await createMint(
+      provider.connection,
+      provider.wallet.payer,
+      originalAuthority,
+      null,
+      6
+    );
+    const [vault] = PublicKey.findProgramAddressSync(
+      [Buffer.from("vault"), mint.toBuffer()],
+      tokenbankProgram.programId
+    );
+    const registerIx = await tokenbankProgram.methods
+      .registerMint()
+      .accounts({
+        bank: tokenbankPDA,
+        vault,
+        mint,
+        tokenbankAta: getAssociatedTokenAddressSync(mint, tokenbankPDA, true),
+        treasury: PublicKey.findProgramAddressSync(
+          [Buffer.from("treasury"), mint.toBuffer()],
+          tokenbankProgram.programId
+        )[0],
+        authority: multisigPDA,
+        payer: vaultPDA,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .instruction();
+    const register = await propose(registerIx);
+    await approve(register.proposal, member3);
+    await execute(register.proposal, register.remainingAccounts);
+
+    const registered = await tokenbankProgram.account.vault.fetch(vault);
+    assert.equal(registered.mint.toBase58(), mint.toBase58());
+
+    // 通过提案把 authority 交还
+    const proposeIx = await tokenbankProgram.methods
+      .proposeAuthority(originalAuthority)
+      .accounts({
+        bank: tokenbankPDA,
+        authority: multisigPDA,
+      })
+      .instruction();
+    const handBack = await propose(proposeIx);
+    await approve(handBack.proposal, member2);
+    await execute(handBack.proposal, handBack.remainingAccounts);
+
+    await tokenbankProgram.methods
+      .acceptAuthority()
+      .accounts({
+        bank: tokenbankPDA,
+        newAuthority: originalAuthority,
+      })
+      .rpc();
+
+    bank = await tokenbankProgram.account.bank.fetch(tokenbankPDA);
+    assert.equal(bank.authority.toBase58(), originalAuthority.toBase58());
+  });
+});
@@ -5,17 +5,27 @@ import {
   PublicKey,
   Keypair,
   SystemProgram,
+  Transaction,
   SYSVAR_RENT_PUBKEY,
   LAMPORTS_PER_SOL,
 } from "@solana/web3.js";
 import {
   TOKEN_PROGRAM_ID,
+  TOKEN_2022_PROGRAM_ID,
+  ExtensionType,
+  createInitializeMintInstruction,
+  createInitializeTransferFeeConfigInstruction,
+  getMintLen,
   createMint,
   createAccount,
   mintTo,
   getAccount,
   getAssociatedTokenAddress,
   createAssociatedTokenAccount,
+  transfer,
+  NATIVE_MINT,
+  ASSOCIATED_TOKEN_PROGRAM_ID,
+  getAssociatedTokenAddressSync,
 } from "@solana/spl-token";
 // import { Tokenbank } from "../target/types/tokenbank";
 
@@ -31,10 +41,11 @@ describe("tokenbank", () => {
   let bankTokenAccount: PublicKey;
   let userTokenAccount: PublicKey;
   let userPDA: PublicKey;
+  let vaultPDA: PublicKey;
+  let treasuryPDA: PublicKey;
 
   const mintAuthority = Keypair.generate();
   const user = Keypair.generate();
-  const bankTokenAccountKeypair = Keypair.generate();
 
   before(async () => {
     // 为用户空投 SOL
@@ -69,14 +80,11 @@ describe("tokenbank", () => {
       program.programId
     );
 
-    // 创建银行代币账户
-    bankTokenAccount = await createAccount(
-      provider.connection,
-      provider.wallet.payer,
+    // 银行代币账户是 bank PDA 的 ATA，由 registerMint 创建
+    bankTokenAccount = getAssociatedTokenAddressSync(
       mint,
       bankPDA,
-      bankTokenAccountKeypair,
-      {commitment: "confirmed"}, 
+      true,
       TOKEN_PROGRAM_ID
     );
 
@@ -103,27 +111,82 @@ describe("tokenbank", () => {
       TOKEN_PROGRAM_ID
     );
 
+    [vaultPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("vault"), mint.toBuffer()],
+      program.programId
+    );
+    [treasuryPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("treasury"), mint.toBuffer()],
+      program.programId
+    );
+
+    // 用户账本按 (owner, mint) 派生
     [userPDA] = PublicKey.findProgramAddressSync(
-      [Buffer.from("user"), user.publicKey.toBuffer()],
+      [Buffer.from("user"), user.publicKey.toBuffer(), mint.toBuffer()],
       program.programId
     );
   });
 
   it("初始化TokenBank", async () => {
+    try {
+      await program.methods
+        .initialize()
+        .accounts({
+          bank: bankPDA,
+          authority: provider.wallet.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+    } catch (error: any) {
+      // multisig 测试可能已经初始化过 bank，只验证 authority
+      if (!error.message?.includes("already in use")) {
+        throw error;
+      }
+    }
+
+    const bankAccount = await program.account.bank.fetch(bankPDA);
+    assert.equal(
+      bankAccount.authority.toBase58(),
+      provider.wallet.publicKey.toBase58()
+    );
+  });
+
+  it("将 mint 加入白名单", async () => {
     await program.methods
-      .initialize()
+      .registerMint()
       .accounts({
         bank: bankPDA,
+        vault: vaultPDA,
+        mint: mint,
+        tokenbankAta: bankTokenAccount,
+        treasury: treasuryPDA,
         authority: provider.wallet.publicKey,
+        payer: provider.wallet.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
         systemProgram: SystemProgram.programId,
       })
       .rpc();
 
-    const bankAccount = await program.account.bank.fetch(bankPDA);
-    assert.equal(
-      bankAccount.authority.toBase58(),
-      provider.wallet.publicKey.toBase58()
-    );
+    const vault = await program.account.vault.fetch(vaultPDA);
+    assert.equal(vault.mint.toBase58(), mint.toBase58());
+    assert.isTrue(vault.allowed);
+  });
+
+  it("非 authority 不能暂停", async () => {
+    try {
+      await program.methods
+        .setPaused(true)
+        .accounts({
+          bank: bankPDA,
+          authority: user.publicKey,
+        })
+        .signers([user])
+        .rpc();
+      assert.fail("Expected Unauthorized");
+    } catch (err) {
+      assert.include(err.toString(), "Unauthorized");
+    }
   });
 
   it("创建用户账户", async () => {
@@ -131,6 +194,7 @@ describe("tokenbank", () => {
       .createUserAccount()
       .accounts({
         userAccount: userPDA,
+        mint: mint,
         owner: user.publicKey,
         systemProgram: SystemProgram.programId,
       })
@@ -138,7 +202,9 @@ describe("tokenbank", () => {
       .rpc();
 
     const userAccount = await program.account.userAccount.fetch(userPDA);
-    assert.equal(userAccount.depositAmount.toNumber(), 0);
+    assert.equal(userAccount.shares.toNumber(), 0);
+    assert.equal(userAccount.owner.toBase58(), user.publicKey.toBase58());
+    assert.equal(userAccount.mint.toBase58(), mint.toBase58());
   });
 
   it("存款", async () => {
@@ -148,12 +214,15 @@ describe("tokenbank", () => {
       .deposit(depositAmount)
       .accounts({
         bank: bankPDA,
+        vault: vaultPDA,
         userAccount: userPDA,
         mint: mint,
         depositorAta: userTokenAccount,
         tokenbankAta: bankTokenAccount,
         depositor: user.publicKey,
+        payer: user.publicKey,
         tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
       })
       .signers([user])
       .rpc();
@@ -166,13 +235,102 @@ describe("tokenbank", () => {
       TOKEN_PROGRAM_ID
     );
 
-    assert.equal(userAccount.depositAmount.toNumber(), depositAmount.toNumber());
+    assert.equal(userAccount.shares.toNumber(), depositAmount.toNumber()); // 空金库按 1:1 铸造份额
     assert.equal(
       bankTokenAccountInfo.amount.toString(),
       depositAmount.toString()
     );
   });
 
+  it("拒绝非标准 ATA 的金库账户", async () => {
+    // bank PDA 持有的普通代币账户，不是标准 ATA
+    const spoofedVault = await createAccount(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      bankPDA,
+      Keypair.generate(),
+      {commitment: "confirmed"},
+      TOKEN_PROGRAM_ID
+    );
+
+    try {
+      await program.methods
+        .deposit(new anchor.BN(1_000))
+        .accounts({
+          bank: bankPDA,
+          vault: vaultPDA,
+          userAccount: userPDA,
+          mint: mint,
+          depositorAta: userTokenAccount,
+          tokenbankAta: spoofedVault,
+          depositor: user.publicKey,
+          payer: user.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+      assert.fail("非标准金库账户不应被接受");
+    } catch (err) {
+      assert.include(err.toString(), "AccountNotAssociatedTokenAccount");
+    }
+  });
+
+  it("预览赎回", async () => {
+    const assets = await program.methods
+      .previewRedeem(new anchor.BN(50_000_000))
+      .accounts({
+        bank: bankPDA,
+        vault: vaultPDA,
+        mint: mint,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .view();
+
+    assert.equal(assets.toNumber(), 50_000_000);
+  });
+
+  it("暂停后拒绝存款", async () => {
+    await program.methods
+      .setPaused(true)
+      .accounts({
+        bank: bankPDA,
+        authority: provider.wallet.publicKey,
+      })
+      .rpc();
+
+    try {
+      await program.methods
+        .deposit(new anchor.BN(1_000))
+        .accounts({
+          bank: bankPDA,
+          vault: vaultPDA,
+          userAccount: userPDA,
+          mint: mint,
+          depositorAta: userTokenAccount,
+          tokenbankAta: bankTokenAccount,
+          depositor: user.publicKey,
+          payer: user.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+      assert.fail("Expected BankPaused");
+    } catch (err) {
+      assert.include(err.toString(), "BankPaused");
+    }
+
+    await program.methods
+      .setPaused(false)
+      .accounts({
+        bank: bankPDA,
+        authority: provider.wallet.publicKey,
+      })
+      .rpc();
+  });
+
   it("提取", async () => {
     const depositAmount = new anchor.BN(100_000_000); // 100 tokens
     const withdrawAmount = new anchor.BN(50_000_000); // 50 tokens
@@ -190,10 +348,12 @@ describe("tokenbank", () => {
       .withdraw(withdrawAmount)
       .accounts({
         bank: bankPDA,
+        vault: vaultPDA,
         userAccount: userPDA,
         mint: mint,
         tokenbankAta: bankTokenAccount,
         receiverAta: userTokenAccount,
+        treasury: treasuryPDA,
         receiver: user.publicKey,
         tokenProgram: TOKEN_PROGRAM_ID,
       })
@@ -218,7 +378,7 @@ describe("tokenbank", () => {
     );
 
     assert.equal(
-      userAccount.depositAmount.toNumber(),
+      userAccount.shares.toNumber(),
       depositAmount.sub(withdrawAmount).toNumber()
     );
     assert.equal(
@@ -238,10 +398,12 @@ describe("tokenbank", () => {
       .withdraw(withdrawAmount)
       .accounts({
         bank: bankPDA,
+        vault: vaultPDA,
         userAccount: userPDA,
         mint: mint,
         tokenbankAta: bankTokenAccount,
         receiverAta: userTokenAccount,
+        treasury: treasuryPDA,
         receiver: user.publicKey,
         tokenProgram: TOKEN_PROGRAM_ID,
       })
@@ -252,6 +414,7 @@ describe("tokenbank", () => {
       .closeUserAccount()
       .accounts({
         userAccount: userPDA,
+        mint: mint,
         owner: user.publicKey,
       })
       .signers([user])
@@ -264,4 +427,730 @@ describe("tokenbank", () => {
       assert.include(err.toString(), "Account does not exist");
     }
   });
-}); 
+
+  it("流动性凭证可转让并由持有者赎回", async () => {
+    const [receiptMint] = PublicKey.findProgramAddressSync(
+      [Buffer.from("receipt"), mint.toBuffer()],
+      program.programId
+    );
+
+    await program.methods
+      .createReceiptMint()
+      .accounts({
+        bank: bankPDA,
+        vault: vaultPDA,
+        mint: mint,
+        receiptMint: receiptMint,
+        authority: provider.wallet.publicKey,
+        payer: provider.wallet.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
+
+    const userReceiptAta = await createAssociatedTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      receiptMint,
+      user.publicKey,
+      {commitment: "confirmed"},
+      TOKEN_PROGRAM_ID
+    );
+
+    await program.methods
+      .depositLiquid(new anchor.BN(10_000_000))
+      .accounts({
+        bank: bankPDA,
+        vault: vaultPDA,
+        mint: mint,
+        receiptMint: receiptMint,
+        depositorAta: userTokenAccount,
+        depositorReceiptAta: userReceiptAta,
+        tokenbankAta: bankTokenAccount,
+        depositor: user.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([user])
+      .rpc();
+
+    // 凭证转给另一个持有者，由其赎回
+    const holder = Keypair.generate();
+    const holderReceiptAta = await createAssociatedTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      receiptMint,
+      holder.publicKey,
+      {commitment: "confirmed"},
+      TOKEN_PROGRAM_ID
+    );
+    const holderTokenAccount = await createAssociatedTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      holder.publicKey,
+      {commitment: "confirmed"},
+      TOKEN_PROGRAM_ID
+    );
+    await transfer(
+      provider.connection,
+      provider.wallet.payer,
+      userReceiptAta,
+      holderReceiptAta,
+      user,
+      10_000_000,
+      [],
+      {commitment: "confirmed"},
+      TOKEN_PROGRAM_ID
+    );
+
+    await program.methods
+      .redeemReceipt(new anchor.BN(10_000_000))
+      .accounts({
+        bank: bankPDA,
+        vault: vaultPDA,
+        mint: mint,
+        receiptMint: receiptMint,
+        holderReceiptAta: holderReceiptAta,
+        tokenbankAta: bankTokenAccount,
+        receiverAta: holderTokenAccount,
+        treasury: treasuryPDA,
+        holder: holder.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([holder])
+      .rpc();
+
+    const holderBalance = await getAccount(
+      provider.connection,
+      holderTokenAccount,
+      "confirmed",
+      TOKEN_PROGRAM_ID
+    );
+    assert.equal(holderBalance.amount.toString(), "10000000");
+  });
+
+  it("存款按份额领取奖励", async () => {
+    const rewardMint = await createMint(
+      provider.connection,
+      provider.wallet.payer,
+      provider.wallet.publicKey,
+      null,
+      9,
+      undefined,
+      {commitment: "confirmed"},
+      TOKEN_PROGRAM_ID
+    );
+    const funderAta = await createAssociatedTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      rewardMint,
+      provider.wallet.publicKey,
+      {commitment: "confirmed"},
+      TOKEN_PROGRAM_ID
+    );
+    await mintTo(
+      provider.connection,
+      provider.wallet.payer,
+      rewardMint,
+      funderAta,
+      provider.wallet.payer,
+      1_000_000_000,
+      [],
+      {commitment: "confirmed"},
+      TOKEN_PROGRAM_ID
+    );
+    const [rewardVault] = PublicKey.findProgramAddressSync(
+      [Buffer.from("reward_vault"), mint.toBuffer()],
+      program.programId
+    );
+
+    await program.methods
+      .initializeRewards(new anchor.BN(1_000_000)) // 每秒 0.001 token
+      .accounts({
+        bank: bankPDA,
+        vault: vaultPDA,
+        rewardMint: rewardMint,
+        rewardVault: rewardVault,
+        authority: provider.wallet.publicKey,
+        payer: provider.wallet.publicKey,
+        rewardTokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
+
+    await program.methods
+      .fundRewards(new anchor.BN(1_000_000_000))
+      .accounts({
+        bank: bankPDA,
+        vault: vaultPDA,
+        rewardMint: rewardMint,
+        rewardVault: rewardVault,
+        funderAta: funderAta,
+        authority: provider.wallet.publicKey,
+        rewardTokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+
+    // 之前的账本已关闭，重新创建后存款
+    await program.methods
+      .createUserAccount()
+      .accounts({
+        userAccount: userPDA,
+        mint: mint,
+        owner: user.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([user])
+      .rpc();
+    await program.methods
+      .deposit(new anchor.BN(10_000_000))
+      .accounts({
+        bank: bankPDA,
+        vault: vaultPDA,
+        userAccount: userPDA,
+        mint: mint,
+        depositorAta: userTokenAccount,
+        tokenbankAta: bankTokenAccount,
+        depositor: user.publicKey,
+        payer: user.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([user])
+      .rpc();
+
+    await new Promise((resolve) => setTimeout(resolve, 2_000));
+
+    const userRewardAta = await createAssociatedTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      rewardMint,
+      user.publicKey,
+      {commitment: "confirmed"},
+      TOKEN_PROGRAM_ID
+    );
+    await program.methods
+      .claimRewards()
+      .accounts({
+        bank: bankPDA,
+        vault: vaultPDA,
+        mint: mint,
+        userAccount: userPDA,
+        rewardMint: rewardMint,
+        rewardVault: rewardVault,
+        receiverRewardAta: userRewardAta,
+        owner: user.publicKey,
+        rewardTokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([user])
+      .rpc();
+
+    const rewardBalance = await getAccount(
+      provider.connection,
+      userRewardAta,
+      "confirmed",
+      TOKEN_PROGRAM_ID
+    );
+    assert.isTrue(rewardBalance.amount > BigInt(0));
+  });
+
+  it("锁定存款到期前不能提取", async () => {
+    const lockId = new anchor.BN(1);
+    const [lockPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("lock"), userPDA.toBuffer(), lockId.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+    const unlockTs = new anchor.BN(Math.floor(Date.now() / 1000) + 3600);
+
+    await program.methods
+      .depositLocked(new anchor.BN(20_000_000), lockId, {
+        until: { unlockTs },
+      })
+      .accounts({
+        deposit: {
+          bank: bankPDA,
+          vault: vaultPDA,
+          userAccount: userPDA,
+          mint: mint,
+          depositorAta: userTokenAccount,
+          tokenbankAta: bankTokenAccount,
+          depositor: user.publicKey,
+          payer: user.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        },
+        lockRecord: lockPDA,
+        payer: user.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([user])
+      .rpc();
+
+    const userAccount = await program.account.userAccount.fetch(userPDA);
+    const lockRecord = await program.account.lockRecord.fetch(lockPDA);
+    assert.equal(userAccount.activeLocks, 1);
+    assert.equal(
+      userAccount.lockedShares.toString(),
+      lockRecord.totalShares.toString()
+    );
+
+    try {
+      await program.methods
+        .redeem(userAccount.shares)
+        .accounts({
+          bank: bankPDA,
+          vault: vaultPDA,
+          userAccount: userPDA,
+          mint: mint,
+          tokenbankAta: bankTokenAccount,
+          receiverAta: userTokenAccount,
+          treasury: treasuryPDA,
+          receiver: user.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user])
+        .rpc();
+      assert.fail("锁定份额不应被赎回");
+    } catch (err) {
+      assert.include(err.toString(), "FundsLocked");
+    }
+  });
+
+  it("Token-2022 转账手续费按实际到账记账", async () => {
+    // 创建带 transfer-fee 扩展的 Token-2022 mint：手续费 1%，上限 1 token
+    const mint2022Keypair = Keypair.generate();
+    const mint2022 = mint2022Keypair.publicKey;
+    const mintLen = getMintLen([ExtensionType.TransferFeeConfig]);
+    const lamports =
+      await provider.connection.getMinimumBalanceForRentExemption(mintLen);
+
+    const createMintTx = new Transaction().add(
+      SystemProgram.createAccount({
+        fromPubkey: provider.wallet.publicKey,
+        newAccountPubkey: mint2022,
+        space: mintLen,
+        lamports,
+        programId: TOKEN_2022_PROGRAM_ID,
+      }),
+      createInitializeTransferFeeConfigInstruction(
+        mint2022,
+        mintAuthority.publicKey,
+        mintAuthority.publicKey,
+        100, // 1%
+        BigInt(1_000_000_000),
+        TOKEN_2022_PROGRAM_ID
+      ),
+      createInitializeMintInstruction(
+        mint2022,
+        9,
+        mintAuthority.publicKey,
+        null,
+        TOKEN_2022_PROGRAM_ID
+      )
+    );
+    await provider.sendAndConfirm(createMintTx, [mint2022Keypair]);
+
+    const bankAta2022 = getAssociatedTokenAddressSync(
+      mint2022,
+      bankPDA,
+      true,
+      TOKEN_2022_PROGRAM_ID
+    );
+    const userAta2022 = await createAssociatedTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      mint2022,
+      user.publicKey,
+      {commitment: "confirmed"},
+      TOKEN_2022_PROGRAM_ID
+    );
+    await mintTo(
+      provider.connection,
+      provider.wallet.payer,
+      mint2022,
+      userAta2022,
+      mintAuthority,
+      1_000_000_000,
+      [],
+      {commitment: "confirmed"},
+      TOKEN_2022_PROGRAM_ID
+    );
+
+    const [userPDA2022] = PublicKey.findProgramAddressSync(
+      [Buffer.from("user"), user.publicKey.toBuffer(), mint2022.toBuffer()],
+      program.programId
+    );
+    const [vaultPDA2022] = PublicKey.findProgramAddressSync(
+      [Buffer.from("vault"), mint2022.toBuffer()],
+      program.programId
+    );
+
+    await program.methods
+      .registerMint()
+      .accounts({
+        bank: bankPDA,
+        vault: vaultPDA2022,
+        mint: mint2022,
+        tokenbankAta: bankAta2022,
+        treasury: PublicKey.findProgramAddressSync(
+          [Buffer.from("treasury"), mint2022.toBuffer()],
+          program.programId
+        )[0],
+        authority: provider.wallet.publicKey,
+        payer: provider.wallet.publicKey,
+        tokenProgram: TOKEN_2022_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
+
+    await program.methods
+      .createUserAccount()
+      .accounts({
+        userAccount: userPDA2022,
+        mint: mint2022,
+        owner: user.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([user])
+      .rpc();
+
+    await program.methods
+      .deposit(new anchor.BN(100_000_000))
+      .accounts({
+        bank: bankPDA,
+        vault: vaultPDA2022,
+        userAccount: userPDA2022,
+        mint: mint2022,
+        depositorAta: userAta2022,
+        tokenbankAta: bankAta2022,
+        depositor: user.publicKey,
+        payer: user.publicKey,
+        tokenProgram: TOKEN_2022_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([user])
+      .rpc();
+
+    // 100 tokens 扣除 1% 手续费后金库实际收到 99 tokens
+    const userAccount = await program.account.userAccount.fetch(userPDA2022);
+    assert.equal(userAccount.shares.toNumber(), 99_000_000);
+  });
+
+  it("直接存取 SOL，自动包装 wSOL", async () => {
+    const [vaultPDASol] = PublicKey.findProgramAddressSync(
+      [Buffer.from("vault"), NATIVE_MINT.toBuffer()],
+      program.programId
+    );
+    const [userPDASol] = PublicKey.findProgramAddressSync(
+      [Buffer.from("user"), user.publicKey.toBuffer(), NATIVE_MINT.toBuffer()],
+      program.programId
+    );
+    const [wsolPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("wsol"), user.publicKey.toBuffer()],
+      program.programId
+    );
+    const [treasurySol] = PublicKey.findProgramAddressSync(
+      [Buffer.from("treasury"), NATIVE_MINT.toBuffer()],
+      program.programId
+    );
+
+    const bankWsolAccount = getAssociatedTokenAddressSync(
+      NATIVE_MINT,
+      bankPDA,
+      true,
+      TOKEN_PROGRAM_ID
+    );
+
+    await program.methods
+      .registerMint()
+      .accounts({
+        bank: bankPDA,
+        vault: vaultPDASol,
+        mint: NATIVE_MINT,
+        tokenbankAta: bankWsolAccount,
+        treasury: treasurySol,
+        authority: provider.wallet.publicKey,
+        payer: provider.wallet.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
+
+    // 首次存入 SOL 时自动创建 UserAccount
+    const depositAmount = new anchor.BN(LAMPORTS_PER_SOL);
+    await program.methods
+      .depositSol(depositAmount)
+      .accounts({
+        bank: bankPDA,
+        vault: vaultPDASol,
+        userAccount: userPDASol,
+        mint: NATIVE_MINT,
+        wsolAccount: wsolPDA,
+        tokenbankAta: bankWsolAccount,
+        depositor: user.publicKey,
+        payer: user.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([user])
+      .rpc();
+
+    let userAccount = await program.account.userAccount.fetch(userPDASol);
+    assert.equal(userAccount.shares.toString(), depositAmount.toString());
+    // 临时 wSOL 账户在指令结束前已关闭
+    assert.isNull(await provider.connection.getAccountInfo(wsolPDA));
+
+    const beforeLamports = await provider.connection.getBalance(user.publicKey);
+    await program.methods
+      .withdrawSol(depositAmount)
+      .accounts({
+        bank: bankPDA,
+        vault: vaultPDASol,
+        userAccount: userPDASol,
+        mint: NATIVE_MINT,
+        wsolAccount: wsolPDA,
+        tokenbankAta: bankWsolAccount,
+        treasury: treasurySol,
+        receiver: user.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([user])
+      .rpc();
+
+    userAccount = await program.account.userAccount.fetch(userPDASol);
+    const afterLamports = await provider.connection.getBalance(user.publicKey);
+    assert.equal(userAccount.shares.toNumber(), 0);
+    // 扣除交易费后仍应收回接近 1 SOL
+    assert.isAbove(afterLamports - beforeLamports, LAMPORTS_PER_SOL - 10_000);
+  });
+
+  it("提取收取手续费并由 authority 归集", async () => {
+    try {
+      await program.methods
+        .setWithdrawFee(501)
+        .accounts({
+          bank: bankPDA,
+          authority: provider.wallet.publicKey,
+        })
+        .rpc();
+      assert.fail("手续费不应超过上限");
+    } catch (err) {
+      assert.include(err.toString(), "FeeTooHigh");
+    }
+
+    await program.methods
+      .setWithdrawFee(100) // 1%
+      .accounts({
+        bank: bankPDA,
+        authority: provider.wallet.publicKey,
+      })
+      .rpc();
+
+    await program.methods
+      .deposit(new anchor.BN(10_000_000))
+      .accounts({
+        bank: bankPDA,
+        vault: vaultPDA,
+        userAccount: userPDA,
+        mint: mint,
+        depositorAta: userTokenAccount,
+        tokenbankAta: bankTokenAccount,
+        depositor: user.publicKey,
+        payer: user.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([user])
+      .rpc();
+
+    const beforeBalance = (
+      await getAccount(provider.connection, userTokenAccount, "confirmed", TOKEN_PROGRAM_ID)
+    ).amount;
+    await program.methods
+      .withdraw(new anchor.BN(5_000_000))
+      .accounts({
+        bank: bankPDA,
+        vault: vaultPDA,
+        userAccount: userPDA,
+        mint: mint,
+        tokenbankAta: bankTokenAccount,
+        receiverAta: userTokenAccount,
+        treasury: treasuryPDA,
+        receiver: user.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([user])
+      .rpc();
+    const afterBalance = (
+      await getAccount(provider.connection, userTokenAccount, "confirmed", TOKEN_PROGRAM_ID)
+    ).amount;
+    assert.equal(afterBalance - beforeBalance, BigInt(4_950_000));
+
+    const feeDestination = await createAssociatedTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      provider.wallet.publicKey,
+      {commitment: "confirmed"},
+      TOKEN_PROGRAM_ID
+    );
+    await program.methods
+      .collectFees()
+      .accounts({
+        bank: bankPDA,
+        mint: mint,
+        treasury: treasuryPDA,
+        destination: feeDestination,
+        authority: provider.wallet.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+
+    const collected = await getAccount(
+      provider.connection,
+      feeDestination,
+      "confirmed",
+      TOKEN_PROGRAM_ID
+    );
+    assert.equal(collected.amount, BigInt(50_000));
+
+    await program.methods
+      .setWithdrawFee(0)
+      .accounts({
+        bank: bankPDA,
+        authority: provider.wallet.publicKey,
+      })
+      .rpc();
+  });
+
+  it("被授权的 spender 代为提取并扣减额度", async () => {
+    const spender = Keypair.generate();
+    const [allowancePDA] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("allowance"),
+        user.publicKey.toBuffer(),
+        mint.toBuffer(),
+        spender.publicKey.toBuffer(),
+      ],
+      program.programId
+    );
+    const spenderTokenAccount = await createAssociatedTokenAccount(
+      provider.connection,
+      provider.wallet.payer,
+      mint,
+      spender.publicKey,
+      {commitment: "confirmed"},
+      TOKEN_PROGRAM_ID
+    );
+
+    await program.methods
+      .approve(
+        new anchor.BN(1_500_000),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 3600)
+      )
+      .accounts({
+        allowance: allowancePDA,
+        mint: mint,
+        spender: spender.publicKey,
+        owner: user.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([user])
+      .rpc();
+
+    const withdrawFrom = (amount: number) =>
+      program.methods
+        .withdrawFrom(new anchor.BN(amount))
+        .accounts({
+          bank: bankPDA,
+          vault: vaultPDA,
+          userAccount: userPDA,
+          mint: mint,
+          allowance: allowancePDA,
+          tokenbankAta: bankTokenAccount,
+          receiverAta: spenderTokenAccount,
+          treasury: treasuryPDA,
+          owner: user.publicKey,
+          spender: spender.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([spender])
+        .rpc();
+
+    await withdrawFrom(1_000_000);
+    const allowance = await program.account.allowance.fetch(allowancePDA);
+    assert.equal(allowance.amount.toNumber(), 500_000);
+
+    try {
+      await withdrawFrom(1_000_000);
+      assert.fail("超出额度的提取应被拒绝");
+    } catch (err) {
+      assert.include(err.toString(), "InsufficientAllowance");
+    }
+
+    const received = await getAccount(
+      provider.connection,
+      spenderTokenAccount,
+      "confirmed",
+      TOKEN_PROGRAM_ID
+    );
+    assert.equal(received.amount, BigInt(1_000_000));
+
+    await program.methods
+      .revoke()
+      .accounts({
+        allowance: allowancePDA,
+        owner: user.publicKey,
+      })
+      .signers([user])
+      .rpc();
+    assert.isNull(await provider.connection.getAccountInfo(allowancePDA));
+  });
+
+  it("超过金库存款上限的存款被拒绝", async () => {
+    const vault = await program.account.vault.fetch(vaultPDA);
+
+    await program.methods
+      .setVaultLimits(vault.totalAssets, new anchor.BN(0), new anchor.BN(0))
+      .accounts({
+        bank: bankPDA,
+        vault: vaultPDA,
+        authority: provider.wallet.publicKey,
+      })
+      .rpc();
+
+    try {
+      await program.methods
+        .deposit(new anchor.BN(1_000_000))
+        .accounts({
+          bank: bankPDA,
+          vault: vaultPDA,
+          userAccount: userPDA,
+          mint: mint,
+          depositorAta: userTokenAccount,
+          tokenbankAta: bankTokenAccount,
+          depositor: user.publicKey,
+          payer: user.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+      assert.fail("超过上限的存款应被拒绝");
+    } catch (err) {
+      assert.include(err.toString(), "DepositCapExceeded");
+    }
+
+    await program.methods
+      .setVaultLimits(new anchor.BN(0), new anchor.BN(0), new anchor.BN(0))
+      .accounts({
+        bank: bankPDA,
+        vault: vaultPDA,
+        authority: provider.wallet.publicKey,
+      })
+      .rpc();
+  });
+});